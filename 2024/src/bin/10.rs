@@ -2,7 +2,8 @@ use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
-use std::collections::{HashSet, VecDeque};
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::result::Result::Ok;
@@ -19,56 +20,145 @@ const TEST: &str = r#"89010123
 01329801
 10456732"#;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeightEncoding {
+    // A single digit per cell
+    Digits,
+    // `a` to `z` for heights 0 to 25
+    Letters,
+    // Whitespace separated numbers, so heights may have several digits
+    Numbers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MapFormat {
+    encoding: HeightEncoding,
+    impassable: Vec<String>,
+}
+
+impl Default for MapFormat {
+    fn default() -> Self {
+        Self {
+            encoding: HeightEncoding::Digits,
+            impassable: vec![".".to_string()],
+        }
+    }
+}
+
+impl MapFormat {
+    fn with_encoding(mut self, encoding: HeightEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    fn with_impassable(mut self, symbols: &[&str]) -> Self {
+        self.impassable = symbols.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    fn parse_cell(&self, cell: &str) -> Result<Option<u8>> {
+        if self.impassable.iter().any(|symbol| symbol == cell) {
+            return Ok(None);
+        }
+        let height = match self.encoding {
+            HeightEncoding::Digits => cell
+                .chars()
+                .next()
+                .and_then(|c| c.to_digit(10))
+                .map(|d| d as u8),
+            HeightEncoding::Letters => cell
+                .chars()
+                .next()
+                .filter(char::is_ascii_lowercase)
+                .map(|c| c as u8 - b'a'),
+            HeightEncoding::Numbers => cell.parse::<u8>().ok(),
+        };
+        height
+            .map(Some)
+            .ok_or_else(|| anyhow!("Unknown map symbol: {:?}", cell))
+    }
+
+    fn parse_row(&self, line: &str) -> Result<Vec<Option<u8>>> {
+        match self.encoding {
+            HeightEncoding::Numbers => line
+                .split_whitespace()
+                .map(|cell| self.parse_cell(cell))
+                .collect(),
+            _ => line
+                .chars()
+                .map(|c| self.parse_cell(c.encode_utf8(&mut [0; 4])))
+                .collect(),
+        }
+    }
+}
+
+type StepRule = Box<dyn Fn(u8, u8) -> bool>;
+
+// Trails go from `start` to `end` height, and `step` decides whether a trail
+// may move from one height to the next one.
+struct TrailRule {
+    start: u8,
+    end: u8,
+    step: StepRule,
+}
+
+impl TrailRule {
+    fn new<F: Fn(u8, u8) -> bool + 'static>(start: u8, end: u8, step: F) -> Self {
+        Self {
+            start,
+            end,
+            step: Box::new(step),
+        }
+    }
+
+    fn hiking() -> Self {
+        Self::new(0, 9, |height, next| height.checked_add(1) == Some(next))
+    }
+}
+
 struct TopographyMap {
-    pub topography: Vec<Vec<u8>>,
+    pub topography: Vec<Vec<Option<u8>>>,
     pub area: Rectangle,
+    pub rule: TrailRule,
 }
 
 impl TopographyMap {
     pub fn read<R: BufRead>(input: R) -> Result<Self> {
-        let mut max_i = 0;
-        let mut max_j = 0;
+        Self::read_with(input, &MapFormat::default())
+    }
 
+    pub fn read_with<R: BufRead>(input: R, format: &MapFormat) -> Result<Self> {
         let topography = input
             .lines()
             .map_while(Result::ok)
-            .enumerate()
-            .map(|(i, line)| {
-                max_i = max_i.max(i);
-                max_j = max_j.max(line.len() - 1);
-
-                line.chars()
-                    .map(|c| {
-                        if c as u8 >= b'0' {
-                            c as u8 - b'0'
-                        } else {
-                            c as u8
-                        }
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+            .filter(|line| !line.is_empty())
+            .map(|line| format.parse_row(&line))
+            .collect::<Result<Vec<_>>>()?;
+
+        let max_i = topography.len().saturating_sub(1);
+        let max_j = topography
+            .iter()
+            .map(|row| row.len().saturating_sub(1))
+            .max()
+            .unwrap_or_default();
 
         Ok(Self {
             topography,
             area: ((0, 0), (max_i, max_j)),
+            rule: TrailRule::hiking(),
         })
     }
 
+    pub fn with_rule(mut self, rule: TrailRule) -> Self {
+        self.rule = rule;
+        self
+    }
+
     pub fn score_trails<F: FnMut(&TopographyMap, Position) -> usize>(&self, mut f: F) -> usize {
-        self.topography
-            .iter()
-            .enumerate()
-            .map(|(i, row)| {
-                row.iter()
-                    .enumerate()
-                    .filter(|(_, &c)| c == 0)
-                    .map(|(j, _)| f(self, (i, j)))
-                    .sum::<usize>()
-            })
-            .sum::<usize>()
+        self.trailheads().into_iter().map(|pos| f(self, pos)).sum()
     }
 
+    // Calls `f` for passable neighbors only
     pub fn for_each_neighbor<F: FnMut(Position, u8)>(&self, pos: Position, mut f: F) {
         for dir in [Direction::E, Direction::W, Direction::S, Direction::N] {
             let Some(new_pos) = leap(pos, dir, 1) else {
@@ -77,47 +167,227 @@ impl TopographyMap {
             if !rectangle_includes(&self.area, new_pos) {
                 continue;
             }
-            f(new_pos, self.topography[new_pos.0][new_pos.1]);
+            if let Some(height) = self.height(new_pos) {
+                f(new_pos, height);
+            }
         }
     }
-}
 
-fn score_trail(map: &TopographyMap, start: Position) -> usize {
-    let mut score = 0;
-    let mut queue = VecDeque::from([start]);
-    let mut visited = HashSet::new();
+    fn height(&self, pos: Position) -> Option<u8> {
+        self.topography.get(pos.0)?.get(pos.1).copied().flatten()
+    }
 
-    while let Some(pos) = queue.pop_front() {
-        let height = map.topography[pos.0][pos.1];
-        if height == 9 {
-            score += 1;
-        } else {
-            map.for_each_neighbor(pos, |new_pos, new_height| {
-                if !visited.contains(&new_pos) && new_height == height + 1 {
-                    queue.push_back(new_pos);
-                    visited.insert(new_pos);
+    fn positions(&self) -> impl Iterator<Item = Position> + '_ {
+        self.topography
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| (0..row.len()).map(move |j| (i, j)))
+    }
+
+    fn trailheads(&self) -> Vec<Position> {
+        self.positions()
+            .filter(|pos| self.height(*pos) == Some(self.rule.start))
+            .collect()
+    }
+
+    fn is_summit(&self, pos: Position) -> bool {
+        self.height(pos) == Some(self.rule.end)
+    }
+
+    fn summits(&self) -> Vec<Position> {
+        self.positions()
+            .filter(|pos| self.is_summit(*pos))
+            .collect()
+    }
+
+    fn steps(&self, pos: Position) -> Vec<Position> {
+        let mut steps = vec![];
+        let Some(height) = self.height(pos) else {
+            return steps;
+        };
+        self.for_each_neighbor(pos, |new_pos, new_height| {
+            if (self.rule.step)(height, new_height) {
+                steps.push(new_pos);
+            }
+        });
+        steps
+    }
+
+    fn reverse_steps(&self, pos: Position) -> Vec<Position> {
+        let mut steps = vec![];
+        let Some(height) = self.height(pos) else {
+            return steps;
+        };
+        self.for_each_neighbor(pos, |new_pos, new_height| {
+            if (self.rule.step)(new_height, height) {
+                steps.push(new_pos);
+            }
+        });
+        steps
+    }
+
+    // Number of paths from every cell to any cell matching `is_end`, each
+    // cell is solved once. Step rules that allow going in circles make the
+    // number of paths infinite and are reported as an error, just like counts
+    // too large for `usize`.
+    fn count_paths<E, S>(&self, is_end: E, steps: S) -> Result<Vec<Vec<usize>>>
+    where
+        E: Fn(Position) -> bool,
+        S: Fn(Position) -> Vec<Position>,
+    {
+        #[derive(Clone, Copy)]
+        enum Count {
+            Unknown,
+            InProgress,
+            Known(usize),
+        }
+
+        fn count<E, S>(
+            pos: Position,
+            is_end: &E,
+            steps: &S,
+            memo: &mut [Vec<Count>],
+        ) -> Result<usize>
+        where
+            E: Fn(Position) -> bool,
+            S: Fn(Position) -> Vec<Position>,
+        {
+            match memo[pos.0][pos.1] {
+                Count::Known(paths) => return Ok(paths),
+                Count::InProgress => bail!("Step rule allows a cycle through {:?}", pos),
+                Count::Unknown => {}
+            }
+            memo[pos.0][pos.1] = Count::InProgress;
+            let paths = if is_end(pos) {
+                1
+            } else {
+                let mut paths = 0usize;
+                for next in steps(pos) {
+                    paths = paths
+                        .checked_add(count(next, is_end, steps, memo)?)
+                        .ok_or_else(|| anyhow!("Number of paths from {:?} overflows", pos))?;
+                }
+                paths
+            };
+            memo[pos.0][pos.1] = Count::Known(paths);
+            Ok(paths)
+        }
+
+        let mut memo = self
+            .topography
+            .iter()
+            .map(|row| vec![Count::Unknown; row.len()])
+            .collect::<Vec<_>>();
+        for pos in self.positions() {
+            count(pos, &is_end, &steps, &mut memo)?;
+        }
+        Ok(memo
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|count| match count {
+                        Count::Known(paths) => paths,
+                        _ => 0,
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    fn paths_to_summits(&self) -> Result<Vec<Vec<usize>>> {
+        self.count_paths(|pos| self.is_summit(pos), |pos| self.steps(pos))
+    }
+
+    fn paths_from_trailheads(&self) -> Result<Vec<Vec<usize>>> {
+        self.count_paths(
+            |pos| self.height(pos) == Some(self.rule.start),
+            |pos| self.reverse_steps(pos),
+        )
+    }
+
+    // Number of complete trails passing through every cell
+    fn trails_through(&self) -> Result<Vec<Vec<usize>>> {
+        let up = self.paths_to_summits()?;
+        let down = self.paths_from_trailheads()?;
+        up.iter()
+            .zip(down.iter())
+            .enumerate()
+            .map(|(i, (up, down))| {
+                up.iter()
+                    .zip(down)
+                    .enumerate()
+                    .map(|(j, (u, d))| {
+                        u.checked_mul(*d).ok_or_else(|| {
+                            anyhow!("Number of trails through {:?} overflows", (i, j))
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Trails never visit a cell twice, even if the step rule would allow it
+    fn trails(&self, start: Position) -> Vec<Vec<Position>> {
+        fn extend(map: &TopographyMap, trail: &mut Vec<Position>, trails: &mut Vec<Vec<Position>>) {
+            let pos = *trail.last().unwrap();
+            if map.is_summit(pos) {
+                trails.push(trail.clone());
+                return;
+            }
+            for next in map.steps(pos) {
+                if !trail.contains(&next) {
+                    trail.push(next);
+                    extend(map, trail, trails);
+                    trail.pop();
                 }
-            });
+            }
+        }
+
+        let mut trails = vec![];
+        if self.height(start).is_some() {
+            extend(self, &mut vec![start], &mut trails);
         }
+        trails
     }
 
-    score
+    fn summit_trailheads(&self) -> BTreeMap<Position, BTreeSet<Position>> {
+        let mut reached: BTreeMap<Position, BTreeSet<Position>> = BTreeMap::new();
+        for trailhead in self.trailheads() {
+            let mut stack = vec![trailhead];
+            let mut visited = HashSet::from([trailhead]);
+            while let Some(pos) = stack.pop() {
+                if self.is_summit(pos) {
+                    reached.entry(pos).or_default().insert(trailhead);
+                    continue;
+                }
+                for next in self.steps(pos) {
+                    if visited.insert(next) {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        for summit in self.summits() {
+            reached.entry(summit).or_default();
+        }
+        reached
+    }
 }
 
-fn score_trail_v2(map: &TopographyMap, start: Position) -> usize {
+fn score_trail(map: &TopographyMap, start: Position) -> usize {
     let mut score = 0;
     let mut queue = VecDeque::from([start]);
+    let mut visited = HashSet::from([start]);
 
     while let Some(pos) = queue.pop_front() {
-        let height = map.topography[pos.0][pos.1];
-        if height == 9 {
+        if map.is_summit(pos) {
             score += 1;
         } else {
-            map.for_each_neighbor(pos, |new_pos, new_height| {
-                if new_height == height + 1 {
+            for new_pos in map.steps(pos) {
+                if visited.insert(new_pos) {
                     queue.push_back(new_pos);
                 }
-            });
+            }
         }
     }
 
@@ -146,9 +416,17 @@ fn main() -> Result<()> {
     // region Part 2
     println!("\n=== Part 2 ===");
 
+    fn rating(map: &TopographyMap) -> Result<usize> {
+        let paths = map.paths_to_summits()?;
+        map.trailheads()
+            .into_iter()
+            .try_fold(0usize, |total, (i, j)| total.checked_add(paths[i][j]))
+            .ok_or_else(|| anyhow!("Total rating overflows"))
+    }
+
     fn part2<R: BufRead>(reader: R) -> Result<usize> {
         let map = TopographyMap::read(reader)?;
-        let answer = map.score_trails(score_trail_v2);
+        let answer = rating(&map)?;
         Ok(answer)
     }
 
@@ -159,12 +437,124 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Map formats
+    println!("\n=== Map formats ===");
+
+    // The test map with letters instead of digits and walls instead of the
+    // lowest cells
+    let letters = TEST
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(0) => '#',
+            Some(d) => (b'a' + d as u8) as char,
+            None => c,
+        })
+        .collect::<String>();
+    let format = MapFormat::default()
+        .with_encoding(HeightEncoding::Letters)
+        .with_impassable(&["#"]);
+    let map = TopographyMap::read_with(letters.as_bytes(), &format)?.with_rule(TrailRule::new(
+        1,
+        9,
+        |height, next| height.checked_add(1) == Some(next),
+    ));
+    println!("Letters without trailheads, rating {}", rating(&map)?);
+
+    // The test map with every height raised by 10
+    let numbers = TEST
+        .lines()
+        .map(|line| line.chars().map(|c| c as u32 - '0' as u32 + 10).join(" "))
+        .join("\n");
+    let format = MapFormat::default().with_encoding(HeightEncoding::Numbers);
+    let map = TopographyMap::read_with(numbers.as_bytes(), &format)?.with_rule(TrailRule::new(
+        10,
+        19,
+        |height, next| height.checked_add(1) == Some(next),
+    ));
+    assert_eq!(rating(&map)?, 81);
+    println!("Numbers from 10 to 19, rating {}", rating(&map)?);
+    // endregion
+
+    // region Trail rules
+    println!("\n=== Trail rules ===");
+
+    fn compare_rules<R: BufRead>(reader: R) -> Result<()> {
+        let map = TopographyMap::read(reader)?;
+        let rules = [
+            ("Climb by one", TrailRule::hiking()),
+            (
+                "Climb at most 2",
+                TrailRule::new(0, 9, |height, next| next > height && next - height <= 2),
+            ),
+            (
+                "Descend by one",
+                TrailRule::new(9, 0, |height, next| next.checked_add(1) == Some(height)),
+            ),
+        ];
+        let mut map = map;
+        for (name, rule) in rules {
+            map = map.with_rule(rule);
+            println!(
+                "{}: score {}, rating {}",
+                name,
+                map.score_trails(score_trail),
+                rating(&map)?
+            );
+        }
+        Ok(())
+    }
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(compare_rules(input_file)?);
+    // endregion
+
+    // region Trails
+    println!("\n=== Trails ===");
+
+    fn describe_trails<R: BufRead>(reader: R) -> Result<()> {
+        let map = TopographyMap::read(reader)?;
+        let through = map.trails_through()?;
+        if let Some((pos, trails)) = map
+            .positions()
+            .map(|(i, j)| ((i, j), through[i][j]))
+            .max_by_key(|(_, trails)| *trails)
+        {
+            println!("Busiest cell {:?} with {} trails", pos, trails);
+        }
+
+        let summits = map.summit_trailheads();
+        if let Some((summit, trailheads)) = summits.iter().max_by_key(|(_, heads)| heads.len()) {
+            println!(
+                "Summit {:?} is reached from {} of {} trailheads",
+                summit,
+                trailheads.len(),
+                map.trailheads().len()
+            );
+        }
+        let unreachable = summits.values().filter(|heads| heads.is_empty()).count();
+        println!("Unreachable summits: {} of {}", unreachable, summits.len());
+
+        if let Some(trail) = map
+            .trailheads()
+            .first()
+            .and_then(|start| map.trails(*start).into_iter().next())
+        {
+            println!("First trail: {:?}", trail);
+        }
+        Ok(())
+    }
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(describe_trails(input_file)?);
+    // endregion
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{score_trail, score_trail_v2, TopographyMap};
+    use crate::{score_trail, HeightEncoding, MapFormat, TopographyMap, TrailRule, TEST};
+    use itertools::Itertools;
 
     #[test]
     fn test_score_trail() {
@@ -211,6 +601,155 @@ mod tests {
         .unwrap();
 
         assert_eq!(map.area, ((0, 0), (5, 5)));
-        assert_eq!(score_trail_v2(&map, (0, 0)), 227);
+        assert_eq!(map.paths_to_summits().unwrap()[0][0], 227);
+        assert_eq!(map.trails((0, 0)).len(), 227);
+    }
+
+    #[test]
+    fn test_trails() {
+        let map = TopographyMap::read(TEST.as_bytes()).unwrap();
+        let up = map.paths_to_summits().unwrap();
+        let ratings = map
+            .trailheads()
+            .iter()
+            .map(|(i, j)| up[*i][*j])
+            .collect::<Vec<_>>();
+        assert_eq!(ratings, vec![20, 24, 10, 4, 1, 4, 5, 8, 5]);
+
+        let trails = map.trails((0, 2));
+        assert_eq!(trails.len(), 20);
+        assert!(trails.iter().all(|trail| {
+            trail.len() == 10 && trail.windows(2).all(|w| map.steps(w[0]).contains(&w[1]))
+        }));
+
+        // Every trail is counted once at its trailhead, summit and each cell
+        // in between.
+        let through = map.trails_through().unwrap();
+        let total = ratings.iter().sum::<usize>();
+        let down = map.paths_from_trailheads().unwrap();
+        let summits = map.summits();
+        assert_eq!(
+            summits.iter().map(|(i, j)| down[*i][*j]).sum::<usize>(),
+            total
+        );
+        assert_eq!(through.iter().flatten().sum::<usize>(), total * 10);
+
+        let reached = map.summit_trailheads();
+        assert_eq!(reached.values().map(|heads| heads.len()).sum::<usize>(), 36);
+        for trailhead in map.trailheads() {
+            let summits = reached
+                .values()
+                .filter(|heads| heads.contains(&trailhead))
+                .count();
+            assert_eq!(summits, score_trail(&map, trailhead));
+        }
+    }
+
+    #[test]
+    fn test_map_formats() {
+        let map = TopographyMap::read("0#1\n9.8".as_bytes());
+        assert!(map.is_err());
+
+        let format = MapFormat::default().with_impassable(&["#", "."]);
+        let map = TopographyMap::read_with("0#1\n9.8".as_bytes(), &format).unwrap();
+        assert_eq!(
+            map.topography,
+            vec![vec![Some(0), None, Some(1)], vec![Some(9), None, Some(8)]]
+        );
+
+        let format = MapFormat::default().with_encoding(HeightEncoding::Letters);
+        let map = TopographyMap::read_with("az.\nbc.".as_bytes(), &format).unwrap();
+        assert_eq!(map.topography[0], vec![Some(0), Some(25), None]);
+        assert_eq!(map.area, ((0, 0), (1, 2)));
+
+        let format = MapFormat::default()
+            .with_encoding(HeightEncoding::Numbers)
+            .with_impassable(&["--"]);
+        let map = TopographyMap::read_with("10 11 --\n 9 12 13".as_bytes(), &format)
+            .unwrap()
+            .with_rule(TrailRule::new(9, 13, |height, next| {
+                height.checked_add(1) == Some(next)
+            }));
+        assert_eq!(map.topography[0], vec![Some(10), Some(11), None]);
+        assert_eq!(map.trailheads(), vec![(1, 0)]);
+        assert_eq!(
+            map.trails((1, 0)),
+            vec![vec![(1, 0), (0, 0), (0, 1), (1, 1), (1, 2)]]
+        );
+        assert!(TopographyMap::read_with("10 x".as_bytes(), &format).is_err());
+    }
+
+    #[test]
+    fn test_step_rules() {
+        let map = TopographyMap::read("0246\n1357".as_bytes()).unwrap();
+        assert_eq!(map.score_trails(score_trail), 0);
+
+        let map = map.with_rule(TrailRule::new(0, 7, |height, next| {
+            next > height && next - height <= 2
+        }));
+        assert_eq!(map.score_trails(score_trail), 1);
+        assert_eq!(map.paths_to_summits().unwrap()[0][0], 4);
+
+        let map = TopographyMap::read(TEST.as_bytes())
+            .unwrap()
+            .with_rule(TrailRule::new(9, 0, |height, next| {
+                next.checked_add(1) == Some(height)
+            }));
+        let down = map.paths_to_summits().unwrap();
+        let descents = map
+            .trailheads()
+            .iter()
+            .map(|(i, j)| down[*i][*j])
+            .sum::<usize>();
+        assert_eq!(descents, 81);
+
+        let map = map.with_rule(TrailRule::new(0, 9, |height, next| {
+            height.abs_diff(next) == 1
+        }));
+        assert!(map.paths_to_summits().is_err());
+        assert!(map
+            .trails((0, 2))
+            .iter()
+            .all(|trail| trail.iter().all_unique()));
+
+        // Steps from the highest height stay in range
+        let format = MapFormat::default().with_encoding(HeightEncoding::Numbers);
+        let map = TopographyMap::read_with("253 254 255\n0 255 255".as_bytes(), &format).unwrap();
+        assert_eq!(map.paths_to_summits().unwrap()[0][0], 0);
+        let map = map.with_rule(TrailRule::new(253, 255, |height, next| {
+            height.checked_add(1) == Some(next)
+        }));
+        assert_eq!(map.score_trails(score_trail), 2);
+        let map = map.with_rule(TrailRule::new(255, 253, |height, next| {
+            next.checked_add(1) == Some(height)
+        }));
+        assert_eq!(map.score_trails(score_trail), 2);
+    }
+
+    #[test]
+    fn test_path_count_overflow() {
+        // Heights grow by one per row and column, so every monotone walk
+        // across the 35x35 map is a trail and there are C(68, 34) > 2^64
+        let text = (0..35)
+            .map(|i| (0..35).map(|j| (i + j).to_string()).join(" "))
+            .join("\n");
+        let format = MapFormat::default().with_encoding(HeightEncoding::Numbers);
+        let map = TopographyMap::read_with(text.as_bytes(), &format)
+            .unwrap()
+            .with_rule(TrailRule::new(0, 68, |height, next| {
+                height.checked_add(1) == Some(next)
+            }));
+        assert!(map.paths_to_summits().is_err());
+        assert!(map.trails_through().is_err());
+
+        // One row less keeps C(67, 33) in range
+        let text = text.lines().take(34).join("\n");
+        let map = TopographyMap::read_with(text.as_bytes(), &format)
+            .unwrap()
+            .with_rule(TrailRule::new(0, 67, |height, next| {
+                height.checked_add(1) == Some(next)
+            }));
+        assert_eq!(map.paths_to_summits().unwrap()[0][0], 14_226_520_737_620_288_370);
+        assert!(map.trails_through().is_ok());
     }
 }
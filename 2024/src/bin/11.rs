@@ -2,7 +2,8 @@ use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
-use std::collections::HashMap;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::result::Result::Ok;
@@ -12,6 +13,10 @@ const INPUT_FILE: &str = concatcp!("input/", DAY, ".txt");
 
 const TEST: &str = r#"125 17"#;
 
+const PRIME: u128 = 1_000_000_007;
+
+const STANDARD_RULES: &str = "0 -> 1; split; * 2024";
+
 fn count_digits(mut n: usize) -> usize {
     let mut count = 0;
     while n > 0 {
@@ -33,48 +38,591 @@ fn split_in_half(mut n: usize, n_digits: usize) -> (usize, usize) {
     (n, m)
 }
 
-fn blink(stones: impl Iterator<Item = usize>, blinks: usize) -> u128 {
-    let mut calculator = Calculator::new();
-    stones
-        .into_iter()
-        .map(|stone| calculator.calculate(stone, blinks))
-        .sum()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OverflowKind {
+    Value,
+    Count,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StoneOverflow {
+    kind: OverflowKind,
+    stone: String,
+    blink: usize,
+}
+
+impl StoneOverflow {
+    fn new(kind: OverflowKind, stone: impl std::fmt::Display, blink: usize) -> Self {
+        Self {
+            kind,
+            stone: stone.to_string(),
+            blink,
+        }
+    }
+}
+
+impl std::fmt::Display for StoneOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let kind = match self.kind {
+            OverflowKind::Value => "value",
+            OverflowKind::Count => "count",
+        };
+        write!(
+            f,
+            "stone {} overflow for stone {} at blink {}",
+            kind, self.stone, self.blink
+        )
+    }
+}
+
+impl std::error::Error for StoneOverflow {}
+
+trait Stone: Clone + Eq + std::hash::Hash + std::fmt::Display {
+    fn from_usize(n: usize) -> Self;
+    fn is_zero(&self) -> bool;
+    fn n_digits(&self) -> usize;
+    fn split_in_half(&self, n_digits: usize) -> (Self, Self);
+    fn checked_mul(&self, factor: usize) -> Option<Self>;
+}
+
+trait Count: Clone + std::fmt::Display {
+    fn one() -> Self;
+    fn zero() -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl Stone for usize {
+    fn from_usize(n: usize) -> Self {
+        n
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn n_digits(&self) -> usize {
+        count_digits(*self)
+    }
+
+    fn split_in_half(&self, n_digits: usize) -> (Self, Self) {
+        split_in_half(*self, n_digits)
+    }
+
+    fn checked_mul(&self, factor: usize) -> Option<Self> {
+        usize::checked_mul(*self, factor)
+    }
+}
+
+impl Stone for u128 {
+    fn from_usize(n: usize) -> Self {
+        n as u128
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn n_digits(&self) -> usize {
+        self.checked_ilog10().map_or(0, |d| d as usize + 1)
+    }
+
+    fn split_in_half(&self, n_digits: usize) -> (Self, Self) {
+        let divisor = 10u128.pow((n_digits / 2) as u32);
+        (self / divisor, self % divisor)
+    }
+
+    fn checked_mul(&self, factor: usize) -> Option<Self> {
+        u128::checked_mul(*self, factor as u128)
+    }
+}
+
+impl Count for u128 {
+    fn one() -> Self {
+        1
+    }
+
+    fn zero() -> Self {
+        0
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u128::checked_add(*self, *other)
+    }
+}
+
+// Arbitrary precision unsigned integer, stored as little-endian decimal digits
+// without leading zeros so that digit counting and splitting stay trivial.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct BigNum(Vec<u8>);
+
+impl BigNum {
+    fn normalized(mut digits: Vec<u8>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigNum(digits)
+    }
+}
+
+impl std::fmt::Display for BigNum {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "0");
+        }
+        let repr = self.0.iter().rev().map(|d| (b'0' + d) as char).join("");
+        write!(f, "{}", repr)
+    }
+}
+
+impl Stone for BigNum {
+    fn from_usize(mut n: usize) -> Self {
+        let mut digits = vec![];
+        while n > 0 {
+            digits.push((n % 10) as u8);
+            n /= 10;
+        }
+        BigNum(digits)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn n_digits(&self) -> usize {
+        self.0.len()
+    }
+
+    fn split_in_half(&self, n_digits: usize) -> (Self, Self) {
+        let (low, high) = self.0.split_at(n_digits / 2);
+        (BigNum(high.to_vec()), BigNum::normalized(low.to_vec()))
+    }
+
+    fn checked_mul(&self, factor: usize) -> Option<Self> {
+        let mut digits = Vec::with_capacity(self.0.len() + 20);
+        let mut carry = 0u128;
+        for d in self.0.iter() {
+            let product = *d as u128 * factor as u128 + carry;
+            digits.push((product % 10) as u8);
+            carry = product / 10;
+        }
+        while carry > 0 {
+            digits.push((carry % 10) as u8);
+            carry /= 10;
+        }
+        Some(BigNum::normalized(digits))
+    }
+}
+
+impl Count for BigNum {
+    fn one() -> Self {
+        BigNum(vec![1])
+    }
+
+    fn zero() -> Self {
+        BigNum(vec![])
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let mut digits = Vec::with_capacity(self.0.len().max(other.0.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.0.len().max(other.0.len()) {
+            let sum = self.0.get(i).unwrap_or(&0) + other.0.get(i).unwrap_or(&0) + carry;
+            digits.push(sum % 10);
+            carry = sum / 10;
+        }
+        if carry > 0 {
+            digits.push(carry);
+        }
+        Some(BigNum(digits))
+    }
+}
+
+fn blink_with<S: Stone, C: Count>(
+    stones: impl Iterator<Item = usize>,
+    blinks: usize,
+) -> Result<C, StoneOverflow> {
+    let mut calculator = Calculator::<S, C>::new();
+    stones.into_iter().try_fold(C::zero(), |total, stone| {
+        let count = calculator.calculate(S::from_usize(stone), blinks)?;
+        total
+            .checked_add(&count)
+            .ok_or_else(|| StoneOverflow::new(OverflowKind::Count, stone, blinks))
+    })
 }
 
-struct Calculator {
-    cache: HashMap<(usize, usize), u128>,
+// Runs on native integers and only falls back to big stone values when they
+// do not fit, so the common case keeps the fast path.
+fn blink(stones: impl Iterator<Item = usize>, blinks: usize) -> Result<u128> {
+    let stones = stones.collect_vec();
+    match blink_with::<usize, u128>(stones.iter().copied(), blinks) {
+        Ok(count) => Ok(count),
+        Err(err) if err.kind == OverflowKind::Value => {
+            Ok(blink_with::<BigNum, u128>(stones.into_iter(), blinks)?)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+struct Calculator<S = usize, C = u128> {
+    cache: HashMap<(S, usize), C>,
 }
 
-impl Calculator {
+impl<S: Stone, C: Count> Calculator<S, C> {
     pub fn new() -> Self {
         Self {
             cache: HashMap::new(),
         }
     }
 
-    pub fn calculate(&mut self, stone: usize, blinks: usize) -> u128 {
+    pub fn calculate(&mut self, stone: S, blinks: usize) -> Result<C, StoneOverflow> {
+        self.calculate_at(stone, blinks, 1)
+    }
+
+    fn calculate_at(&mut self, stone: S, blinks: usize, blink: usize) -> Result<C, StoneOverflow> {
         if blinks == 0 {
-            return 1;
+            return Ok(C::one());
         }
         let key = (stone, blinks);
         if let Some(count) = self.cache.get(&key) {
-            return *count;
+            return Ok(count.clone());
         }
+        let stone = &key.0;
 
-        let count = if stone == 0 {
-            self.calculate(1, blinks - 1)
+        let count = if stone.is_zero() {
+            self.calculate_at(S::from_usize(1), blinks - 1, blink + 1)?
         } else {
-            let n_digits = count_digits(stone);
-            if n_digits % 2 == 0 {
-                let (n1, n2) = split_in_half(stone, n_digits);
-                self.calculate(n1, blinks - 1) + self.calculate(n2, blinks - 1)
+            let n_digits = stone.n_digits();
+            if n_digits.is_multiple_of(2) {
+                let (n1, n2) = stone.split_in_half(n_digits);
+                let c1 = self.calculate_at(n1, blinks - 1, blink + 1)?;
+                let c2 = self.calculate_at(n2, blinks - 1, blink + 1)?;
+                c1.checked_add(&c2)
+                    .ok_or_else(|| StoneOverflow::new(OverflowKind::Count, stone, blink))?
             } else {
-                self.calculate(stone * 2024, blinks - 1)
+                let next = stone
+                    .checked_mul(2024)
+                    .ok_or_else(|| StoneOverflow::new(OverflowKind::Value, stone, blink))?;
+                self.calculate_at(next, blinks - 1, blink + 1)?
             }
         };
 
-        self.cache.insert(key, count);
-        count
+        self.cache.insert(key, count.clone());
+        Ok(count)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    Replace { from: usize, to: usize },
+    SplitEvenDigits,
+    Multiply(usize),
+}
+
+impl Rule {
+    pub fn standard() -> Vec<Rule> {
+        parse_rules(STANDARD_RULES).expect("valid standard rules")
+    }
+
+    pub fn apply(
+        &self,
+        stone: usize,
+        blink: usize,
+    ) -> Result<Option<(usize, Option<usize>)>, StoneOverflow> {
+        let children = match *self {
+            Rule::Replace { from, to } if stone == from => Some((to, None)),
+            Rule::SplitEvenDigits => {
+                let n_digits = count_digits(stone);
+                if n_digits > 0 && n_digits.is_multiple_of(2) {
+                    let (n1, n2) = split_in_half(stone, n_digits);
+                    Some((n1, Some(n2)))
+                } else {
+                    None
+                }
+            }
+            Rule::Multiply(factor) => {
+                let next = stone
+                    .checked_mul(factor)
+                    .ok_or_else(|| StoneOverflow::new(OverflowKind::Value, stone, blink))?;
+                Some((next, None))
+            }
+            _ => None,
+        };
+        Ok(children)
+    }
+}
+
+impl std::str::FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "split" {
+            Ok(Rule::SplitEvenDigits)
+        } else if let Some(factor) = s.strip_prefix('*') {
+            Ok(Rule::Multiply(factor.trim().parse()?))
+        } else if let Some((from, to)) = s.split_once("->") {
+            Ok(Rule::Replace {
+                from: from.trim().parse()?,
+                to: to.trim().parse()?,
+            })
+        } else {
+            bail!("unknown rule: {}", s)
+        }
+    }
+}
+
+fn parse_rules(rules: &str) -> Result<Vec<Rule>> {
+    rules.split(';').map(str::parse).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Distribution {
+    blinks: usize,
+    total: Option<u128>,
+    distinct: usize,
+    largest: usize,
+    // Number of distinct stone values for each multiplicity
+    histogram: BTreeMap<u128, usize>,
+}
+
+#[derive(Clone)]
+struct StoneMultiset {
+    stones: HashMap<usize, u128>,
+    rules: Vec<Rule>,
+    blinks: usize,
+}
+
+impl StoneMultiset {
+    pub fn new(stones: impl Iterator<Item = usize>, rules: Vec<Rule>) -> Self {
+        let mut counts = HashMap::new();
+        for stone in stones {
+            *counts.entry(stone).or_default() += 1;
+        }
+        Self {
+            stones: counts,
+            rules,
+            blinks: 0,
+        }
+    }
+
+    pub fn blink(&mut self) -> Result<(), StoneOverflow> {
+        let blink = self.blinks + 1;
+        let mut next: HashMap<usize, u128> = HashMap::with_capacity(self.stones.len());
+        for (&stone, &count) in self.stones.iter() {
+            let (n1, n2) = self.children(stone)?;
+            for child in std::iter::once(n1).chain(n2) {
+                let total = next.entry(child).or_default();
+                *total = total
+                    .checked_add(count)
+                    .ok_or_else(|| StoneOverflow::new(OverflowKind::Count, stone, blink))?;
+            }
+        }
+        self.stones = next;
+        self.blinks = blink;
+        Ok(())
+    }
+
+    pub fn children(&self, stone: usize) -> Result<(usize, Option<usize>), StoneOverflow> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.apply(stone, self.blinks + 1).transpose())
+            .unwrap_or(Ok((stone, None)))
+    }
+
+    pub fn total(&self) -> Option<u128> {
+        self.stones
+            .values()
+            .try_fold(0u128, |total, count| total.checked_add(*count))
+    }
+
+    pub fn distribution(&self) -> Distribution {
+        let mut histogram = BTreeMap::new();
+        for count in self.stones.values() {
+            *histogram.entry(*count).or_default() += 1;
+        }
+        Distribution {
+            blinks: self.blinks,
+            total: self.total(),
+            distinct: self.stones.len(),
+            largest: self.stones.keys().max().copied().unwrap_or_default(),
+            histogram,
+        }
+    }
+
+    pub fn evolve(&mut self, blinks: usize) -> Result<Vec<Distribution>, StoneOverflow> {
+        (0..blinks)
+            .map(|_| {
+                self.blink()?;
+                Ok(self.distribution())
+            })
+            .collect()
+    }
+}
+
+type Matrix = Vec<Vec<u128>>;
+
+fn add_mul(acc: u128, a: u128, b: u128, modulus: Option<u128>) -> Option<u128> {
+    match modulus {
+        Some(m) => Some(add_mod(acc % m, mul_mod(a, b, m), m)),
+        None => acc.checked_add(a.checked_mul(b)?),
+    }
+}
+
+fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+    if a >= m - b {
+        a - (m - b)
+    } else {
+        a + b
+    }
+}
+
+// Doubles and adds when the product does not fit, so any modulus works
+fn mul_mod(a: u128, b: u128, m: u128) -> u128 {
+    let (mut a, mut b) = (a % m, b % m);
+    if let Some(product) = a.checked_mul(b) {
+        return product % m;
+    }
+    let mut result = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = add_mod(result, a, m);
+        }
+        a = add_mod(a, a, m);
+        b >>= 1;
+    }
+    result
+}
+
+fn mul_matrices(a: &Matrix, b: &Matrix, modulus: Option<u128>) -> Option<Matrix> {
+    let n = b.first().map(Vec::len).unwrap_or_default();
+    let mut result = vec![vec![0u128; n]; a.len()];
+    for (i, row) in a.iter().enumerate() {
+        for (k, &a_ik) in row.iter().enumerate() {
+            if a_ik == 0 {
+                continue;
+            }
+            for (j, &b_kj) in b[k].iter().enumerate() {
+                if b_kj != 0 {
+                    result[i][j] = add_mul(result[i][j], a_ik, b_kj, modulus)?;
+                }
+            }
+        }
+    }
+    Some(result)
+}
+
+// Transitions between stone values once the set of values reachable from the
+// initial stones is closed: `matrix[i][j]` is how many stones of `values[j]`
+// a single stone of `values[i]` turns into after one blink.
+struct TransitionMatrix {
+    values: Vec<usize>,
+    initial: Vec<u128>,
+    matrix: Matrix,
+}
+
+impl TransitionMatrix {
+    pub fn detect(stones: &StoneMultiset, max_blinks: usize) -> Result<Self> {
+        let mut seen: HashSet<usize> = stones.stones.keys().copied().collect();
+        let mut multiset = stones.clone();
+
+        for _ in 0..max_blinks {
+            multiset.blink()?;
+            let n_seen = seen.len();
+            seen.extend(multiset.stones.keys().copied());
+            if seen.len() == n_seen {
+                return Self::build(stones, seen);
+            }
+        }
+
+        bail!("stone values are not closed after {} blinks", max_blinks)
+    }
+
+    fn build(stones: &StoneMultiset, values: HashSet<usize>) -> Result<Self> {
+        let values = values.into_iter().sorted().collect_vec();
+        let index: HashMap<usize, usize> =
+            values.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+
+        let mut matrix = vec![vec![0u128; values.len()]; values.len()];
+        for (i, value) in values.iter().enumerate() {
+            let (n1, n2) = stones.children(*value)?;
+            matrix[i][index[&n1]] += 1;
+            if let Some(n2) = n2 {
+                matrix[i][index[&n2]] += 1;
+            }
+        }
+
+        let initial = values
+            .iter()
+            .map(|value| stones.stones.get(value).copied().unwrap_or_default())
+            .collect();
+
+        Ok(Self {
+            values,
+            initial,
+            matrix,
+        })
+    }
+
+    // Steps blink by blink while that is cheaper than squaring the matrix,
+    // which pays off only for few values and many blinks.
+    pub fn count_after(&self, blinks: u64, modulus: Option<u128>) -> Result<u128> {
+        let overflow = || anyhow!("stone count overflows u128 after {} blinks", blinks);
+
+        let n = self.values.len() as u128;
+        let squarings = (u64::BITS - blinks.leading_zeros()) as u128;
+        if (blinks as u128) < n * n * squarings {
+            return self.step(blinks, modulus).ok_or_else(overflow);
+        }
+        self.power(blinks, modulus).ok_or_else(overflow)
+    }
+
+    fn power(&self, blinks: u64, modulus: Option<u128>) -> Option<u128> {
+        let mut counts = vec![match modulus {
+            Some(m) => self.initial.iter().map(|c| c % m).collect_vec(),
+            None => self.initial.clone(),
+        }];
+        let mut power = self.matrix.clone();
+        let mut exp = blinks;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                counts = mul_matrices(&counts, &power, modulus)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                power = mul_matrices(&power, &power, modulus)?;
+            }
+        }
+
+        counts[0]
+            .iter()
+            .try_fold(0u128, |acc, c| add_mul(acc, *c, 1, modulus))
+    }
+
+    fn step(&self, blinks: u64, modulus: Option<u128>) -> Option<u128> {
+        let transitions = self
+            .matrix
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .positions(|c| *c > 0)
+                    .map(|j| (j, row[j]))
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        let mut counts = self.initial.clone();
+        for _ in 0..blinks {
+            let mut next = vec![0u128; counts.len()];
+            for (i, count) in counts.iter().enumerate().filter(|(_, c)| **c > 0) {
+                for (j, stones) in transitions[i].iter() {
+                    next[*j] = add_mul(next[*j], *count, *stones, modulus)?;
+                }
+            }
+            counts = next;
+        }
+        counts
+            .iter()
+            .try_fold(0u128, |acc, c| add_mul(acc, *c, 1, modulus))
     }
 }
 
@@ -93,7 +641,7 @@ fn main() -> Result<()> {
                 .split_whitespace()
                 .filter_map(|x| x.parse::<usize>().ok()),
             25,
-        );
+        )?;
         Ok(answer)
     }
 
@@ -108,16 +656,25 @@ fn main() -> Result<()> {
     println!("\n=== Part 2 ===");
 
     fn part2<R: BufRead>(reader: R) -> Result<u128> {
-        let answer = blink(
+        let mut stones = StoneMultiset::new(
             reader
                 .lines()
                 .next()
                 .expect("read line")?
                 .split_whitespace()
                 .filter_map(|x| x.parse::<usize>().ok()),
-            75,
+            Rule::standard(),
         );
-        Ok(answer)
+        let distributions = stones.evolve(75)?;
+        if let Some(last) = distributions.last() {
+            println!(
+                "After {} blinks: {} distinct values, largest {}",
+                last.blinks, last.distinct, last.largest
+            );
+        }
+        stones
+            .total()
+            .ok_or_else(|| anyhow!("stone count overflows u128"))
     }
 
     assert_eq!(22938365706844, part2(BufReader::new("0".as_bytes()))?);
@@ -127,12 +684,51 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Large blink numbers
+    println!("\n=== Large blink numbers ===");
+
+    fn count_large<R: BufRead>(reader: R, blinks: u64) -> Result<u128> {
+        let stones = StoneMultiset::new(
+            reader
+                .lines()
+                .next()
+                .expect("read line")?
+                .split_whitespace()
+                .filter_map(|x| x.parse::<usize>().ok()),
+            Rule::standard(),
+        );
+        let transitions = TransitionMatrix::detect(&stones, 1000)?;
+        println!("Closed over {} stone values", transitions.values.len());
+        transitions.count_after(blinks, Some(PRIME))
+    }
+
+    assert_eq!(
+        22938365706844 % PRIME,
+        count_large(BufReader::new("0".as_bytes()), 75)?
+    );
+
+    let result = time_snippet!(count_large(
+        BufReader::new("0".as_bytes()),
+        1_000_000_000_000
+    )?);
+    println!("Result = {} (mod {})", result, PRIME);
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    let result = time_snippet!(count_large(input_file, 10_000)?);
+    println!("Result = {} (mod {})", result, PRIME);
+    // endregion
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{blink, count_digits, split_in_half};
+    use crate::{
+        add_mod, blink, blink_with, count_digits, mul_mod, parse_rules, split_in_half, BigNum,
+        Calculator, OverflowKind, Rule, Stone, StoneMultiset, StoneOverflow, TransitionMatrix,
+        PRIME,
+    };
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_count_digits() {
@@ -152,8 +748,151 @@ mod tests {
     fn test_blink() {
         let initial: Vec<usize> = vec![125, 17];
 
-        assert_eq!(blink(initial.clone().into_iter(), 1), 3);
-        assert_eq!(blink(initial.clone().into_iter(), 2), 4);
-        assert_eq!(blink(initial.clone().into_iter(), 3), 5);
+        assert_eq!(blink(initial.clone().into_iter(), 1).unwrap(), 3);
+        assert_eq!(blink(initial.clone().into_iter(), 2).unwrap(), 4);
+        assert_eq!(blink(initial.clone().into_iter(), 3).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_stone_multiset() {
+        let mut stones = StoneMultiset::new([125, 17].into_iter(), Rule::standard());
+        let distributions = stones.evolve(6).unwrap();
+        assert_eq!(
+            distributions
+                .iter()
+                .map(|d| d.total.unwrap())
+                .collect::<Vec<_>>(),
+            vec![3, 4, 5, 9, 13, 22]
+        );
+
+        // 2097446912 14168 4048 2 0 2 4 40 48 2024 40 48 80 96 2 8 6 7 6 0 3 2
+        let last = &distributions[5];
+        assert_eq!(last.distinct, 15);
+        assert_eq!(last.largest, 2097446912);
+        assert_eq!(last.histogram, BTreeMap::from([(1, 10), (2, 4), (4, 1)]));
+
+        let mut stones = StoneMultiset::new([125, 17].into_iter(), Rule::standard());
+        stones.evolve(25).unwrap();
+        assert_eq!(
+            stones.total().unwrap(),
+            blink([125, 17].into_iter(), 25).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(
+            parse_rules("0 -> 1; split; * 2024").unwrap(),
+            Rule::standard()
+        );
+        assert!(parse_rules("0 -> 1; double").is_err());
+
+        let mut stones =
+            StoneMultiset::new([0, 5].into_iter(), parse_rules("0 -> 7; * 3").unwrap());
+        stones.evolve(2).unwrap();
+        assert_eq!(stones.stones.keys().copied().max(), Some(45));
+        assert_eq!(stones.total(), Some(2));
+    }
+
+    #[test]
+    fn test_transition_matrix() {
+        let stones = StoneMultiset::new([125, 17].into_iter(), Rule::standard());
+        let transitions = TransitionMatrix::detect(&stones, 100).unwrap();
+        assert_eq!(transitions.values.len(), 76);
+        assert_eq!(transitions.count_after(0, None).unwrap(), 2);
+        assert_eq!(transitions.count_after(6, None).unwrap(), 22);
+        assert_eq!(transitions.count_after(25, None).unwrap(), 55312);
+
+        let stones = StoneMultiset::new([0].into_iter(), Rule::standard());
+        let transitions = TransitionMatrix::detect(&stones, 100).unwrap();
+        assert_eq!(transitions.count_after(75, None).unwrap(), 22938365706844);
+        assert_eq!(
+            transitions.count_after(75, Some(PRIME)).unwrap(),
+            22938365706844 % PRIME
+        );
+        assert!(transitions.count_after(1000, None).is_err());
+        assert_eq!(
+            transitions
+                .count_after(1_000_000_000_000, Some(PRIME))
+                .unwrap(),
+            808184161
+        );
+
+        // Stepping and squaring agree, also for a modulus too large for
+        // plain products
+        let huge = (1 << 127) - 1;
+        for (blinks, modulus) in [(75, None), (500, Some(PRIME)), (500, Some(huge))] {
+            assert_eq!(
+                transitions.step(blinks, modulus),
+                transitions.power(blinks, modulus)
+            );
+        }
+        assert_eq!(mul_mod(huge - 1, huge - 1, huge), 1);
+        assert_eq!(mul_mod(1 << 100, 1 << 30, huge), 8);
+        assert_eq!(add_mod(huge - 1, huge - 2, huge), huge - 3);
+
+        let stones = StoneMultiset::new([1].into_iter(), parse_rules("* 2").unwrap());
+        assert!(TransitionMatrix::detect(&stones, 30).is_err());
+    }
+
+    #[test]
+    fn test_big_num() {
+        let n = BigNum::from_usize(2097446912);
+        assert_eq!(n.to_string(), "2097446912");
+        assert_eq!(n.n_digits(), 10);
+
+        let (n1, n2) = n.split_in_half(10);
+        assert_eq!(
+            (n1.to_string(), n2.to_string()),
+            ("20974".into(), "46912".into())
+        );
+
+        let (n1, n2) = BigNum::from_usize(253000).split_in_half(6);
+        assert_eq!((n1.to_string(), n2.to_string()), ("253".into(), "0".into()));
+        assert!(n2.is_zero());
+
+        let n = BigNum::from_usize(usize::MAX).checked_mul(2024).unwrap();
+        assert_eq!(n.to_string(), (usize::MAX as u128 * 2024).to_string());
+    }
+
+    #[test]
+    fn test_calculator_backends() {
+        let stones = [125, 17];
+        assert_eq!(blink_with::<usize, u128>(stones.into_iter(), 25), Ok(55312));
+        assert_eq!(blink_with::<u128, u128>(stones.into_iter(), 25), Ok(55312));
+        assert_eq!(
+            blink_with::<BigNum, BigNum>(stones.into_iter(), 25).map(|c| c.to_string()),
+            Ok("55312".to_string())
+        );
+
+        // 17 digits, so the first blink multiplies it past usize::MAX
+        let stone = 99_999_999_999_999_999;
+        let mut calculator = Calculator::<usize, u128>::new();
+        assert_eq!(
+            calculator.calculate(stone, 3),
+            Err(StoneOverflow {
+                kind: OverflowKind::Value,
+                stone: stone.to_string(),
+                blink: 1,
+            })
+        );
+        assert_eq!(
+            blink_with::<BigNum, u128>([stone].into_iter(), 3),
+            blink_with::<u128, u128>([stone].into_iter(), 3)
+        );
+        assert_eq!(
+            blink([stone].into_iter(), 3).unwrap(),
+            blink_with::<u128, u128>([stone].into_iter(), 3).unwrap()
+        );
+
+        let mut stones = StoneMultiset::new([stone].into_iter(), Rule::standard());
+        assert_eq!(
+            stones.blink(),
+            Err(StoneOverflow {
+                kind: OverflowKind::Value,
+                stone: stone.to_string(),
+                blink: 1,
+            })
+        );
     }
 }
@@ -1,9 +1,10 @@
+use adv_code_2024::export::{Exporter, Palette};
 use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
 use itertools::Itertools;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::result::Result::Ok;
@@ -69,7 +70,9 @@ impl Region {
     }
 }
 
-fn collect_regions(garden: &Garden) -> Vec<Region> {
+// Index of the region every plot belongs to, regions are numbered in the
+// order they are first met scanning the garden row by row.
+fn label_regions(garden: &Garden) -> Vec<Vec<usize>> {
     let mut region_indices = garden
         .plots
         .iter()
@@ -77,7 +80,7 @@ fn collect_regions(garden: &Garden) -> Vec<Region> {
         .collect::<Vec<_>>();
 
     let mut queue = VecDeque::new();
-    let mut regions = Vec::new();
+    let mut regions = 0;
 
     for (i, row) in garden.plots.iter().enumerate() {
         for (j, plot) in row.iter().enumerate() {
@@ -87,85 +90,622 @@ fn collect_regions(garden: &Garden) -> Vec<Region> {
 
             queue.clear();
             queue.push_front((i, j));
-            let mut region = Region {
-                plot: *plot,
-                ..Default::default()
-            };
             while let Some(pos) = queue.pop_front() {
                 if region_indices[pos.0][pos.1].is_some() {
                     continue;
                 }
-                region.area += 1;
-                region_indices[pos.0][pos.1] = Some(regions.len());
+                region_indices[pos.0][pos.1] = Some(regions);
 
                 for dir in [Direction::N, Direction::E, Direction::W, Direction::S] {
                     if let Some(next_pos) = leap_in_bounds(pos, dir, 1, &garden.area) {
                         let next_plot = garden.plots[next_pos.0][next_pos.1];
-                        if next_plot != *plot {
-                            region.perimeter += 1;
-                        } else if region_indices[next_pos.0][next_pos.1].is_none() {
+                        if next_plot == *plot && region_indices[next_pos.0][next_pos.1].is_none() {
                             queue.push_back(next_pos);
                         }
-                    } else {
-                        region.perimeter += 1;
                     }
                 }
             }
 
-            regions.push(region);
+            regions += 1;
         }
     }
 
-    // Calculate region sides
+    region_indices
+        .into_iter()
+        .map(|row| row.into_iter().map(Option::unwrap).collect())
+        .collect()
+}
+
+fn collect_regions(garden: &Garden) -> Vec<Region> {
+    let labels = label_regions(garden);
+
+    let mut regions = Vec::new();
     for (i, row) in garden.plots.iter().enumerate() {
         for (j, plot) in row.iter().enumerate() {
-            let Some(region_idx) = region_indices[i][j] else {
-                continue;
-            };
-            let Some(region) = regions.get_mut(region_idx) else {
-                continue;
+            let idx = labels[i][j];
+            if idx == regions.len() {
+                regions.push(Region {
+                    plot: *plot,
+                    ..Default::default()
+                });
+            }
+
+            let region = &mut regions[idx];
+            region.area += 1;
+            region.perimeter += fences(garden, (i, j));
+        }
+    }
+
+    // Calculate region sides
+    for (i, row) in labels.iter().enumerate() {
+        for (j, idx) in row.iter().enumerate() {
+            regions[*idx].sides += corners(garden, (i, j));
+        }
+    }
+
+    regions
+}
+
+// Number of fence segments around the plot
+fn fences(garden: &Garden, pos: Position) -> usize {
+    let plot = garden.plots[pos.0][pos.1];
+    [Direction::N, Direction::E, Direction::W, Direction::S]
+        .into_iter()
+        .filter(|dir| {
+            leap_in_bounds(pos, *dir, 1, &garden.area)
+                .is_none_or(|(x, y)| garden.plots[x][y] != plot)
+        })
+        .count()
+}
+
+// Number of region corners at the plot, the sides of a region are as many as
+// its corners
+fn corners(garden: &Garden, pos: Position) -> usize {
+    let plot = garden.plots[pos.0][pos.1];
+    let plot_at = |dir| leap_in_bounds(pos, dir, 1, &garden.area).map(|(x, y)| garden.plots[x][y]);
+
+    let mut corners = 0;
+    for dir in [Direction::N, Direction::E, Direction::W, Direction::S] {
+        match (plot_at(dir), plot_at(dir.turn_right())) {
+            (Some(p1), Some(p2)) if p2 != plot && p1 != plot => {
+                corners += 1;
+            }
+            (Some(p1), None) if p1 != plot => {
+                corners += 1;
+            }
+            (None, Some(p2)) if p2 != plot => {
+                corners += 1;
+            }
+            (None, None) => {
+                corners += 1;
+            }
+
+            (Some(p1), Some(p2)) if p2 == plot && p1 == plot => match plot_at(dir.turn_45_deg()) {
+                Some(p3) if p3 != plot => {
+                    corners += 1;
+                }
+                None => {
+                    corners += 1;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    corners
+}
+
+// Regions kept up to date while single plots are replanted. Every plot
+// contributes its fences and corners to its region, and replanting a plot only
+// changes the contributions of the plots around it, so these are taken out
+// before the change and added back afterwards. Only regions that are merged or
+// split are walked over.
+struct DynamicGarden {
+    garden: Garden,
+    labels: Vec<Vec<usize>>,
+    regions: HashMap<usize, Region>,
+    next_label: usize,
+}
+
+impl DynamicGarden {
+    fn new(garden: Garden) -> Self {
+        let labels = label_regions(&garden);
+        let regions = collect_regions(&garden)
+            .into_iter()
+            .enumerate()
+            .collect::<HashMap<_, _>>();
+        Self {
+            next_label: regions.len(),
+            garden,
+            labels,
+            regions,
+        }
+    }
+
+    fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+
+    fn region_at(&self, pos: Position) -> &Region {
+        &self.regions[&self.labels[pos.0][pos.1]]
+    }
+
+    fn set_plot(&mut self, pos: Position, plot: char) {
+        let old_label = self.labels[pos.0][pos.1];
+        if self.garden.plots[pos.0][pos.1] == plot {
+            return;
+        }
+
+        let window = self.window(pos);
+        for cell in window.iter() {
+            self.update_region(*cell, |region, fences, corners| {
+                region.perimeter -= fences;
+                region.sides -= corners;
+            });
+        }
+        self.regions.get_mut(&old_label).unwrap().area -= 1;
+        self.garden.plots[pos.0][pos.1] = plot;
+        self.labels[pos.0][pos.1] = usize::MAX;
+
+        // The old region may fall apart, every part that does not reach the
+        // remaining neighbours is moved to a region of its own
+        let mut pending = self.neighbours(pos, old_label);
+        while pending.len() > 1 {
+            let start = pending.remove(0);
+            let (cells, connected) = self.explore(start, old_label, &mut pending);
+            if !connected {
+                let label = self.relabel(&cells, &window, old_label);
+                self.regions.get_mut(&label).unwrap().plot = self.garden.plots[start.0][start.1];
+            }
+        }
+        if self.regions[&old_label].area == 0 {
+            self.regions.remove(&old_label);
+        }
+
+        // Bordering regions of the new plot are joined into the largest one
+        let mut joined = self
+            .neighbours_with_plot(pos, plot)
+            .into_iter()
+            .map(|cell| (self.labels[cell.0][cell.1], cell))
+            .unique_by(|(label, _)| *label)
+            .collect_vec();
+        joined.sort_by_key(|(label, _)| self.regions[label].area);
+        let label = match joined.pop() {
+            Some((label, _)) => {
+                for (other, start) in joined {
+                    let (cells, _) = self.explore(start, other, &mut vec![]);
+                    self.relabel(&cells, &window, label);
+                    self.regions.remove(&other);
+                }
+                label
+            }
+            None => {
+                self.next_label += 1;
+                self.regions.insert(
+                    self.next_label - 1,
+                    Region {
+                        plot,
+                        ..Default::default()
+                    },
+                );
+                self.next_label - 1
+            }
+        };
+        self.labels[pos.0][pos.1] = label;
+        self.regions.get_mut(&label).unwrap().area += 1;
+
+        for cell in window.iter() {
+            self.update_region(*cell, |region, fences, corners| {
+                region.perimeter += fences;
+                region.sides += corners;
+            });
+        }
+    }
+
+    // Plots whose fences or corners depend on the given one
+    fn window(&self, pos: Position) -> Vec<Position> {
+        let rows = pos.0.saturating_sub(1)..=(pos.0 + 1).min(self.garden.area.1 .0);
+        let columns = pos.1.saturating_sub(1)..=(pos.1 + 1).min(self.garden.area.1 .1);
+        rows.cartesian_product(columns).collect()
+    }
+
+    fn update_region(&mut self, pos: Position, update: impl Fn(&mut Region, usize, usize)) {
+        let fences = fences(&self.garden, pos);
+        let corners = corners(&self.garden, pos);
+        let region = self.regions.get_mut(&self.labels[pos.0][pos.1]).unwrap();
+        update(region, fences, corners);
+    }
+
+    fn neighbours(&self, pos: Position, label: usize) -> Vec<Position> {
+        [Direction::N, Direction::E, Direction::W, Direction::S]
+            .into_iter()
+            .filter_map(|dir| leap_in_bounds(pos, dir, 1, &self.garden.area))
+            .filter(|(x, y)| self.labels[*x][*y] == label)
+            .collect()
+    }
+
+    fn neighbours_with_plot(&self, pos: Position, plot: char) -> Vec<Position> {
+        [Direction::N, Direction::E, Direction::W, Direction::S]
+            .into_iter()
+            .filter_map(|dir| leap_in_bounds(pos, dir, 1, &self.garden.area))
+            .filter(|(x, y)| self.garden.plots[*x][*y] == plot)
+            .collect()
+    }
+
+    // Walks the plots with the label connected to `start`, stopping early
+    // once every target has been reached, as the walk is then incomplete
+    fn explore(
+        &self,
+        start: Position,
+        label: usize,
+        targets: &mut Vec<Position>,
+    ) -> (Vec<Position>, bool) {
+        let stop_early = !targets.is_empty();
+        let mut cells = vec![];
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(pos) = queue.pop_front() {
+            targets.retain(|target| *target != pos);
+            if stop_early && targets.is_empty() {
+                return (cells, true);
+            }
+            cells.push(pos);
+            for next in self.neighbours(pos, label) {
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        (cells, targets.is_empty())
+    }
+
+    // Moves plots to another region, or to a new one if the label is the
+    // region they are in now, together with their area and contributions
+    fn relabel(&mut self, cells: &[Position], window: &[Position], label: usize) -> usize {
+        let from = self.labels[cells[0].0][cells[0].1];
+        let to = if from == label {
+            self.next_label += 1;
+            self.next_label - 1
+        } else {
+            label
+        };
+
+        let mut moved = Region::default();
+        for cell in cells {
+            if !window.contains(cell) {
+                moved.perimeter += fences(&self.garden, *cell);
+                moved.sides += corners(&self.garden, *cell);
+            }
+            moved.area += 1;
+            self.labels[cell.0][cell.1] = to;
+        }
+
+        let source = self.regions.get_mut(&from).unwrap();
+        source.area -= moved.area;
+        source.perimeter -= moved.perimeter;
+        source.sides -= moved.sides;
+        let target = self.regions.entry(to).or_default();
+        target.area += moved.area;
+        target.perimeter += moved.perimeter;
+        target.sides += moved.sides;
+        to
+    }
+}
+
+type Polygon = Vec<Position>;
+
+// Region boundaries as polygons over plot corners, where corner `(i, j)` is
+// the top left corner of plot `(i, j)`. Outer boundaries run clockwise and
+// holes run counterclockwise, and only vertices where the fence turns are
+// kept, so every vertex starts a new side.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Outline {
+    outer: Polygon,
+    holes: Vec<Polygon>,
+}
+
+impl Outline {
+    fn outer_sides(&self) -> usize {
+        self.outer.len()
+    }
+
+    fn inner_sides(&self) -> usize {
+        self.holes.iter().map(Vec::len).sum()
+    }
+
+    fn sides(&self) -> usize {
+        self.outer_sides() + self.inner_sides()
+    }
+
+    fn area(&self) -> usize {
+        let area = shoelace(&self.outer) + self.holes.iter().map(|h| shoelace(h)).sum::<isize>();
+        area as usize
+    }
+}
+
+// Signed area, positive for polygons running clockwise on screen
+fn shoelace(polygon: &[Position]) -> isize {
+    let doubled = polygon
+        .iter()
+        .circular_tuple_windows()
+        .map(|(a, b)| a.1 as isize * b.0 as isize - b.1 as isize * a.0 as isize)
+        .sum::<isize>();
+    doubled / 2
+}
+
+fn collect_outlines(garden: &Garden) -> Vec<Outline> {
+    trace_outlines(&label_regions(garden), &garden.area)
+}
+
+fn trace_outlines(labels: &[Vec<usize>], area: &Rectangle) -> Vec<Outline> {
+    let regions = labels.iter().flatten().max().map_or(0, |max| max + 1);
+
+    // Fence edges between corners, directed so that the region is on the
+    // right hand side
+    let mut edges = vec![HashMap::<Position, Vec<Position>>::new(); regions];
+    for (i, row) in labels.iter().enumerate() {
+        for (j, idx) in row.iter().enumerate() {
+            let same = |dir| {
+                leap_in_bounds((i, j), dir, 1, area).is_some_and(|(x, y)| labels[x][y] == *idx)
             };
+            let fences = [
+                (Direction::N, (i, j), (i, j + 1)),
+                (Direction::E, (i, j + 1), (i + 1, j + 1)),
+                (Direction::S, (i + 1, j + 1), (i + 1, j)),
+                (Direction::W, (i + 1, j), (i, j)),
+            ];
+            for (dir, from, to) in fences {
+                if !same(dir) {
+                    edges[*idx].entry(from).or_default().push(to);
+                }
+            }
+        }
+    }
 
-            let pos = (i, j);
-            for dir in [Direction::N, Direction::E, Direction::W, Direction::S] {
-                let p1 = leap_in_bounds(pos, dir, 1, &garden.area).map(|(x, y)| garden.plots[x][y]);
-                let p2 = leap_in_bounds(pos, dir.turn_right(), 1, &garden.area)
-                    .map(|(x, y)| garden.plots[x][y]);
+    edges
+        .into_iter()
+        .map(|edges| {
+            let mut outline = Outline::default();
+            for polygon in trace_polygons(edges) {
+                if shoelace(&polygon) > 0 {
+                    outline.outer = polygon;
+                } else {
+                    outline.holes.push(polygon);
+                }
+            }
+            outline
+        })
+        .collect()
+}
 
-                match (p1, p2) {
-                    (Some(p1), Some(p2)) if p2 != *plot && p1 != *plot => {
-                        region.sides += 1;
-                    }
-                    (Some(p1), None) if p1 != *plot => {
-                        region.sides += 1;
-                    }
-                    (None, Some(p2)) if p2 != *plot => {
-                        region.sides += 1;
-                    }
-                    (None, None) => {
-                        region.sides += 1;
-                    }
+// Chains directed fence edges into closed loops. Where plots touch only
+// diagonally a loop can pass the same corner twice, such walks are split
+// there into simple polygons.
+fn trace_polygons(mut edges: HashMap<Position, Vec<Position>>) -> Vec<Polygon> {
+    let heading = |from: Position, to: Position| {
+        (
+            to.0 as isize - from.0 as isize,
+            to.1 as isize - from.1 as isize,
+        )
+    };
 
-                    (Some(p1), Some(p2)) if p2 == *plot && p1 == *plot => {
-                        let p3 = leap_in_bounds(pos, dir.turn_45_deg(), 1, &garden.area)
-                            .map(|(x, y)| garden.plots[x][y]);
-                        match p3 {
-                            Some(p3) if p3 != *plot => {
-                                region.sides += 1;
-                            }
-                            None => {
-                                region.sides += 1;
-                            }
-                            _ => {}
-                        }
-                    }
-                    _ => {}
+    let mut polygons = vec![];
+    while let Some(start) = edges.keys().min().copied() {
+        let mut loop_vertices = vec![start];
+        let mut current = start;
+        let mut direction: Option<(isize, isize)> = None;
+        loop {
+            let targets = edges.get_mut(&current).unwrap();
+            let next_idx = match direction {
+                Some((di, dj)) if targets.len() > 1 => targets
+                    .iter()
+                    .position(|to| heading(current, *to) == (dj, -di))
+                    .unwrap_or(0),
+                _ => 0,
+            };
+            let next = targets.swap_remove(next_idx);
+            if targets.is_empty() {
+                edges.remove(&current);
+            }
+
+            direction = Some(heading(current, next));
+            current = next;
+            if current == start {
+                break;
+            }
+            loop_vertices.push(current);
+        }
+
+        let mut walk: Vec<Position> = vec![];
+        let mut on_walk = HashSet::new();
+        for vertex in loop_vertices {
+            if !on_walk.insert(vertex) {
+                let split = walk.iter().position(|v| *v == vertex).unwrap();
+                let closed = walk.split_off(split);
+                for v in &closed[1..] {
+                    on_walk.remove(v);
                 }
+                polygons.push(closed);
             }
+            walk.push(vertex);
         }
+        polygons.push(walk);
     }
 
-    regions
+    // Keep only the corners where the fence turns, starting from the top left
+    // one
+    polygons
+        .into_iter()
+        .map(|vertices| {
+            let mut polygon = vertices
+                .iter()
+                .circular_tuple_windows()
+                .filter(|(a, b, c)| heading(**a, **b) != heading(**b, **c))
+                .map(|(_, b, _)| *b)
+                .collect_vec();
+            let first = polygon.iter().position_min().unwrap_or_default();
+            polygon.rotate_left(first);
+            polygon
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Border {
+    fence: usize,
+    sides: usize,
+}
+
+// Which regions border each other and which are enclosed by another one
+struct RegionGraph {
+    labels: Vec<Vec<usize>>,
+    regions: Vec<Region>,
+    outlines: Vec<Outline>,
+    // Fence shared by every pair of bordering regions, lower index first
+    borders: BTreeMap<(usize, usize), Border>,
+    // Region whose hole directly contains the region
+    parents: Vec<Option<usize>>,
+}
+
+impl RegionGraph {
+    fn new(garden: &Garden) -> Self {
+        let labels = label_regions(garden);
+        let regions = collect_regions(garden);
+        let outlines = trace_outlines(&labels, &garden.area);
+
+        // A fence separates the plots on its two sides, a side continues
+        // while both plots keep their regions
+        let mut borders = BTreeMap::<_, Border>::new();
+        let mut add_fence = |(a, b): (Position, Position), previous: Option<(usize, usize)>| {
+            let pair = (labels[a.0][a.1], labels[b.0][b.1]);
+            if pair.0 == pair.1 {
+                return None;
+            }
+            let border = borders
+                .entry((pair.0.min(pair.1), pair.0.max(pair.1)))
+                .or_default();
+            border.fence += 1;
+            if previous != Some(pair) {
+                border.sides += 1;
+            }
+            Some(pair)
+        };
+        // Horizontal fences are scanned along rows, vertical ones along columns
+        for (i, row) in labels.iter().enumerate().skip(1) {
+            let mut previous = None;
+            for j in 0..row.len() {
+                previous = add_fence(((i - 1, j), (i, j)), previous);
+            }
+        }
+        for column in 0..labels.first().map_or(0, |row| row.len().saturating_sub(1)) {
+            let mut previous = None;
+            for i in 0..labels.len() {
+                previous = add_fence(((i, column), (i, column + 1)), previous);
+            }
+        }
+
+        // The top left plot of a region is inside every hole that encloses
+        // the region, the smallest of them is the direct parent
+        let mut parents = vec![None; regions.len()];
+        for (idx, outline) in outlines.iter().enumerate() {
+            let plot = outline.outer[0];
+            parents[idx] = outlines
+                .iter()
+                .enumerate()
+                .flat_map(|(parent, o)| o.holes.iter().map(move |hole| (parent, hole)))
+                .filter(|(_, hole)| encloses(hole, plot))
+                .min_by_key(|(_, hole)| -shoelace(hole))
+                .map(|(parent, _)| parent);
+        }
+
+        Self {
+            labels,
+            regions,
+            outlines,
+            borders,
+            parents,
+        }
+    }
+
+    fn border(&self, a: usize, b: usize) -> Option<Border> {
+        self.borders.get(&(a.min(b), a.max(b))).copied()
+    }
+
+    fn neighbours(&self, idx: usize) -> Vec<(usize, Border)> {
+        self.borders
+            .iter()
+            .filter_map(|((a, b), border)| match idx {
+                _ if *a == idx => Some((*b, *border)),
+                _ if *b == idx => Some((*a, *border)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn children(&self, idx: usize) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|child| self.parents[*child] == Some(idx))
+            .collect()
+    }
+
+    // Discounted price of the sides shared with every neighbour
+    fn neighbour_prices(&self, idx: usize) -> Vec<(usize, usize)> {
+        let area = self.regions[idx].area;
+        self.neighbours(idx)
+            .into_iter()
+            .map(|(neighbour, border)| (neighbour, area * border.sides))
+            .collect()
+    }
+
+    // Region made by joining two bordering regions, keeping the plot of the
+    // first one
+    fn merged(&self, a: usize, b: usize) -> Option<Region> {
+        let border = self.border(a, b)?;
+        let (first, second) = (self.regions[a], self.regions[b]);
+
+        // Only the plots around both regions are traced again
+        let corners = self.outlines[a].outer.iter().chain(&self.outlines[b].outer);
+        let (top, bottom) = corners.clone().map(|c| c.0).minmax().into_option()?;
+        let (left, right) = corners.map(|c| c.1).minmax().into_option()?;
+        let labels = self.labels[top..bottom]
+            .iter()
+            .map(|row| {
+                row[left..right]
+                    .iter()
+                    .map(|l| usize::from(*l != a && *l != b))
+                    .collect_vec()
+            })
+            .collect_vec();
+        let area = ((0, 0), (bottom - top - 1, right - left - 1));
+        let outline = &trace_outlines(&labels, &area)[0];
+
+        Some(Region {
+            plot: first.plot,
+            area: first.area + second.area,
+            perimeter: first.perimeter + second.perimeter - 2 * border.fence,
+            sides: outline.sides(),
+        })
+    }
+
+    // Change of the total discounted price when two regions are merged
+    fn merge_savings(&self, a: usize, b: usize) -> Option<isize> {
+        let merged = self.merged(a, b)?;
+        let before = self.regions[a].discounted_price() + self.regions[b].discounted_price();
+        Some(before as isize - merged.discounted_price() as isize)
+    }
+}
+
+// Whether the plot with the given top left corner is inside the polygon,
+// counting the vertical fences to the right of it
+fn encloses(polygon: &[Position], plot: Position) -> bool {
+    polygon
+        .iter()
+        .circular_tuple_windows()
+        .filter(|(a, b)| a.1 == b.1 && a.1 > plot.1)
+        .filter(|(a, b)| (a.0.min(b.0)..a.0.max(b.0)).contains(&plot.0))
+        .count()
+        % 2
+        == 1
 }
 
 fn main() -> Result<()> {
@@ -209,12 +749,156 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Outlines
+    println!("\n=== Outlines ===");
+
+    fn outlines<R: BufRead>(reader: R) -> Result<(usize, usize)> {
+        let garden = Garden::read(reader)?;
+        let regions = collect_regions(&garden);
+        let outlines = collect_outlines(&garden);
+
+        for (region, outline) in regions.iter().zip(&outlines) {
+            ensure!(
+                outline.area() == region.area && outline.sides() == region.sides,
+                "Outline of {:?} does not match the region",
+                region
+            );
+        }
+
+        let outer = outlines.iter().map(Outline::outer_sides).sum();
+        let inner = outlines.iter().map(Outline::inner_sides).sum();
+        Ok((outer, inner))
+    }
+
+    assert_eq!((110, 0), outlines(BufReader::new(TEST.as_bytes()))?);
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    let (outer, inner) = time_snippet!(outlines(input_file)?);
+    println!("Outer sides = {}, inner sides = {}", outer, inner);
+    // endregion
+
+    // region Neighbours
+    println!("\n=== Neighbours ===");
+
+    fn neighbours<R: BufRead>(reader: R) -> Result<(usize, usize, isize)> {
+        let garden = Garden::read(reader)?;
+        let graph = RegionGraph::new(&garden);
+
+        if let Some(idx) = (0..graph.regions.len()).max_by_key(|idx| graph.neighbours(*idx).len()) {
+            let (neighbour, price) = graph
+                .neighbour_prices(idx)
+                .into_iter()
+                .max_by_key(|(_, price)| *price)
+                .unwrap_or_default();
+            println!(
+                "Region {:?} of {} plots borders {} regions and encloses {}, its costliest fence is shared with {:?} for {}",
+                graph.regions[idx].plot,
+                graph.regions[idx].area,
+                graph.neighbours(idx).len(),
+                graph.children(idx).len(),
+                graph.regions[neighbour].plot,
+                price
+            );
+        }
+
+        let enclosed = graph.parents.iter().flatten().count();
+        let best_merge = graph
+            .borders
+            .keys()
+            .filter_map(|(a, b)| graph.merge_savings(*a, *b))
+            .max()
+            .unwrap_or_default();
+        Ok((graph.borders.len(), enclosed, best_merge))
+    }
+
+    assert_eq!((19, 0, 38), neighbours(BufReader::new(TEST.as_bytes()))?);
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    let (pairs, enclosed, best_merge) = time_snippet!(neighbours(input_file)?);
+    println!(
+        "Bordering pairs = {}, enclosed regions = {}, best merge saves {}",
+        pairs, enclosed, best_merge
+    );
+    // endregion
+
+    // region Replanting
+    println!("\n=== Replanting ===");
+
+    // Plot whose replanting with a neighbouring plant saves the most
+    fn replanting<R: BufRead>(reader: R) -> Result<(Position, char, usize, usize)> {
+        let garden = Garden::read(reader)?;
+        let positions = (0..=garden.area.1 .0).cartesian_product(0..=garden.area.1 .1);
+        let mut dynamic = DynamicGarden::new(garden);
+        let total = |dynamic: &DynamicGarden| dynamic.regions().map(Region::price).sum::<usize>();
+        let initial = total(&dynamic);
+
+        let mut best = ((0, 0), '.', 0);
+        for pos in positions {
+            let plot = dynamic.garden.plots[pos.0][pos.1];
+            let neighbours = [Direction::N, Direction::E, Direction::W, Direction::S]
+                .into_iter()
+                .filter_map(|dir| leap_in_bounds(pos, dir, 1, &dynamic.garden.area))
+                .map(|(x, y)| dynamic.garden.plots[x][y])
+                .filter(|p| *p != plot)
+                .unique()
+                .collect_vec();
+            for replanted in neighbours {
+                dynamic.set_plot(pos, replanted);
+                let saved = initial.saturating_sub(total(&dynamic));
+                if saved > best.2 {
+                    best = (pos, replanted, saved);
+                }
+            }
+            dynamic.set_plot(pos, plot);
+        }
+
+        ensure!(
+            total(&dynamic) == initial,
+            "Regions drifted while replanting"
+        );
+
+        let (pos, plot, saved) = best;
+        dynamic.set_plot(pos, plot);
+        Ok((pos, plot, saved, dynamic.region_at(pos).area))
+    }
+
+    assert_eq!(
+        ((2, 5), 'I', 190, 5),
+        replanting(BufReader::new(TEST.as_bytes()))?
+    );
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    let (pos, plot, saved, joined) = time_snippet!(replanting(input_file)?);
+    println!(
+        "Replanting {:?} with {} saves {}, joining a region of {} plots",
+        pos, plot, saved, joined
+    );
+    // endregion
+
+    if std::env::args().any(|arg| arg == "--export") {
+        let garden = Garden::read(BufReader::new(File::open(INPUT_FILE)?))?;
+        let grid = garden
+            .plots
+            .iter()
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<_>>();
+        let exporter = Exporter::new(Palette::default()).with_scale(4);
+        std::fs::create_dir_all("export")?;
+        exporter.write_png(&grid, &mut File::create("export/12-regions.png")?)?;
+        exporter.write_ppm(&grid, &mut File::create("export/12-regions.ppm")?)?;
+        println!("Exported region map to export/12-regions.png");
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{collect_regions, Garden, Region};
+    use crate::{
+        collect_outlines, collect_regions, shoelace, Border, DynamicGarden, Garden, Region,
+        RegionGraph,
+    };
+    use itertools::Itertools;
 
     #[test]
     fn test_collect_regions_case1() {
@@ -418,4 +1102,230 @@ OXXXO"#
             452,
         );
     }
+
+    #[test]
+    fn test_collect_outlines() {
+        let garden = Garden::read(
+            r#".....
+.AAA.
+.A.A.
+.AA..
+.A.A.
+.AAA.
+....."#
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let outlines = collect_outlines(&garden);
+        assert_eq!(outlines.len(), 4);
+
+        let ring = &outlines[1];
+        assert_eq!(
+            ring.outer,
+            vec![
+                (1, 1),
+                (1, 4),
+                (3, 4),
+                (3, 3),
+                (4, 3),
+                (4, 4),
+                (6, 4),
+                (6, 1)
+            ]
+        );
+        assert_eq!(
+            ring.holes,
+            vec![
+                vec![(2, 2), (3, 2), (3, 3), (2, 3)],
+                vec![(4, 2), (5, 2), (5, 3), (4, 3)]
+            ]
+        );
+        assert_eq!((ring.outer_sides(), ring.inner_sides()), (8, 8));
+        assert_eq!(shoelace(&ring.outer), 14);
+        assert_eq!(ring.area(), 12);
+
+        // The surrounding region has the ring as its only hole
+        assert_eq!(
+            (outlines[0].outer_sides(), outlines[0].inner_sides()),
+            (4, 8)
+        );
+        assert_eq!(outlines[0].area(), 21);
+        assert!(outlines[2].holes.is_empty());
+    }
+
+    #[test]
+    fn test_collect_outlines_match_regions() {
+        for input in [
+            "AAAA\nBBCD\nBBCC\nEEEC",
+            "EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE",
+            // Plots touching only diagonally
+            "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA",
+            "AB\nBA",
+        ] {
+            let garden = Garden::read(input.as_bytes()).unwrap();
+            let regions = collect_regions(&garden);
+            let outlines = collect_outlines(&garden);
+            assert_eq!(regions.len(), outlines.len());
+            for (region, outline) in regions.iter().zip(&outlines) {
+                assert_eq!(outline.area(), region.area, "{:?}", region);
+                assert_eq!(outline.sides(), region.sides, "{:?}", region);
+            }
+        }
+    }
+
+    #[test]
+    fn test_region_graph() {
+        let garden = Garden::read(
+            r#".....
+.AAA.
+.A.A.
+.AA..
+.A.A.
+.AAA.
+....."#
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let graph = RegionGraph::new(&garden);
+        assert_eq!(graph.parents, vec![None, Some(0), Some(1), Some(1)]);
+        assert_eq!(graph.children(1), vec![2, 3]);
+        assert_eq!(
+            graph.neighbours(1),
+            vec![
+                (
+                    0,
+                    Border {
+                        fence: 18,
+                        sides: 8
+                    }
+                ),
+                (2, Border { fence: 4, sides: 4 }),
+                (3, Border { fence: 4, sides: 4 })
+            ]
+        );
+        assert_eq!(graph.neighbour_prices(1), vec![(0, 96), (2, 48), (3, 48)]);
+        assert_eq!(graph.border(2, 3), None);
+        assert_eq!(graph.merged(2, 3), None);
+
+        // Filling one hole of the ring
+        assert_eq!(
+            graph.merged(1, 2),
+            Some(Region {
+                plot: 'A',
+                area: 13,
+                perimeter: 22,
+                sides: 12
+            })
+        );
+        assert_eq!(graph.merge_savings(1, 2), Some(12 * 16 + 4 - 13 * 12));
+
+        // Absorbing the ring leaves a rectangle with both islands as holes
+        assert_eq!(
+            graph.merged(0, 1),
+            Some(Region {
+                plot: '.',
+                area: 33,
+                perimeter: 32,
+                sides: 12
+            })
+        );
+    }
+
+    #[test]
+    fn test_region_graph_nesting() {
+        let garden = Garden::read("AAAAA\nABBBA\nABCBA\nABBBA\nAAAAA".as_bytes()).unwrap();
+        let graph = RegionGraph::new(&garden);
+        assert_eq!(graph.parents, vec![None, Some(0), Some(1)]);
+        assert_eq!(
+            graph.neighbours(2),
+            vec![(1, Border { fence: 4, sides: 4 })]
+        );
+        assert_eq!(graph.border(0, 2), None);
+
+        // Regions side by side in one hole are both children of the ring
+        let garden = Garden::read("AAAA\nABCA\nAAAA".as_bytes()).unwrap();
+        let graph = RegionGraph::new(&garden);
+        assert_eq!(graph.parents, vec![None, Some(0), Some(0)]);
+        assert_eq!(graph.border(1, 2), Some(Border { fence: 1, sides: 1 }));
+        assert_eq!(graph.border(0, 1), Some(Border { fence: 3, sides: 3 }));
+    }
+
+    fn sorted(regions: impl Iterator<Item = Region>) -> Vec<(char, usize, usize, usize)> {
+        regions
+            .map(|r| (r.plot, r.area, r.perimeter, r.sides))
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn test_dynamic_garden_split() {
+        let garden = Garden::read("AAAAA\nABBBA\nAAAAA".as_bytes()).unwrap();
+        let mut dynamic = DynamicGarden::new(garden);
+
+        // Cutting the ring twice splits it into two regions
+        dynamic.set_plot((0, 2), 'B');
+        assert_eq!(
+            *dynamic.region_at((1, 2)),
+            Region {
+                plot: 'B',
+                area: 4,
+                perimeter: 10,
+                sides: 8
+            }
+        );
+        dynamic.set_plot((2, 2), 'C');
+        assert_eq!(
+            sorted(dynamic.regions().copied()),
+            vec![
+                ('A', 5, 12, 8),
+                ('A', 5, 12, 8),
+                ('B', 4, 10, 8),
+                ('C', 1, 4, 4)
+            ]
+        );
+        assert_ne!(dynamic.labels[0][0], dynamic.labels[0][4]);
+
+        // Replanting the cut joins the ring again, around the single B plots
+        dynamic.set_plot((2, 2), 'A');
+        dynamic.set_plot((1, 2), 'A');
+        assert_eq!(
+            sorted(dynamic.regions().copied()),
+            vec![
+                ('A', 12, 26, 16),
+                ('B', 1, 4, 4),
+                ('B', 1, 4, 4),
+                ('B', 1, 4, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_garden_matches_collect_regions() {
+        let mut garden = Garden::read(crate::TEST.as_bytes()).unwrap();
+        let mut dynamic = DynamicGarden::new(Garden::read(crate::TEST.as_bytes()).unwrap());
+
+        let mut seed = 11u64;
+        let mut next = |m: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % m) as usize
+        };
+        for _ in 0..500 {
+            let pos = (next(10), next(10));
+            let plot = ['R', 'I', 'C', 'X'][next(4)];
+            garden.plots[pos.0][pos.1] = plot;
+            dynamic.set_plot(pos, plot);
+
+            assert_eq!(
+                sorted(dynamic.regions().copied()),
+                sorted(collect_regions(&garden).into_iter()),
+                "after replanting {:?} with {}",
+                pos,
+                plot
+            );
+        }
+    }
 }
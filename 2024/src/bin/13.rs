@@ -25,75 +25,356 @@ Button A: X+69, Y+23
 Button B: X+27, Y+71
 Prize: X=18641, Y=10279"#;
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+struct Button {
+    name: char,
+    delta: (usize, usize),
+    cost: usize,
+    max_presses: Option<usize>,
+}
+
+impl Button {
+    pub fn new(name: char, delta: (usize, usize)) -> Self {
+        let cost = if name == 'A' { 3 } else { 1 };
+        Self {
+            name,
+            delta,
+            cost,
+            max_presses: None,
+        }
+    }
+
+    fn parse(name: char, data: &str) -> Option<Self> {
+        let mut parts = data.split(", ");
+        let x = parts.next()?.strip_prefix("X+")?.parse::<usize>().ok()?;
+        let y = parts.next()?.strip_prefix("Y+")?.parse::<usize>().ok()?;
+        let mut button = Button::new(name, (x, y));
+        for part in parts {
+            match part.split_once('=')? {
+                ("cost", cost) => button.cost = cost.parse().ok()?,
+                ("max", max) => button.max_presses = Some(max.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(button)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 struct ClawMachine {
-    a: (usize, usize),
-    b: (usize, usize),
+    buttons: Vec<Button>,
     prize: Position,
 }
 
+impl ClawMachine {
+    pub fn with_press_limit(mut self, limit: usize) -> Self {
+        for button in self.buttons.iter_mut() {
+            button.max_presses = Some(button.max_presses.map_or(limit, |max| max.min(limit)));
+        }
+        self
+    }
+}
+
 fn read_claw_machines<R: BufRead>(input: R) -> Result<Vec<ClawMachine>> {
     let mut claw_machines = Vec::new();
-    let mut current_machine = None;
+    let mut current_machine = ClawMachine::default();
 
-    input
+    for line in input
         .lines()
         .map_while(Result::ok)
         .filter(|i| !i.is_empty())
-        .for_each(|line| match line.split_once(": ") {
-            Some(("Button A", data)) => {
-                if let Some((x, y)) = data.split_once(", ") {
-                    let x = x[2..].parse::<usize>().expect("number coordinate");
-                    let y = y[2..].parse::<usize>().expect("number coordinate");
-                    current_machine = Some(ClawMachine {
-                        a: (x, y),
-                        ..Default::default()
-                    });
-                }
-            }
-            Some(("Button B", data)) => {
-                if let Some((x, y)) = data.split_once(", ") {
-                    let x = x[2..].parse::<usize>().expect("number coordinate");
-                    let y = y[2..].parse::<usize>().expect("number coordinate");
-                    if let Some(machine) = current_machine.as_mut() {
-                        machine.b = (x, y);
-                    }
-                }
+    {
+        match line.split_once(": ") {
+            Some((button, data)) if button.starts_with("Button ") => {
+                let name = button.chars().last().expect("button name");
+                let button =
+                    Button::parse(name, data).ok_or_else(|| anyhow!("invalid button: {}", line))?;
+                current_machine.buttons.push(button);
             }
             Some(("Prize", data)) => {
                 if let Some((x, y)) = data.split_once(", ") {
                     let x = x[2..].parse::<usize>().expect("number coordinate");
                     let y = y[2..].parse::<usize>().expect("number coordinate");
-                    if let Some(mut machine) = current_machine.take() {
-                        machine.prize = (x, y);
-                        claw_machines.push(machine);
-                    }
+                    current_machine.prize = (x, y);
+                    claw_machines.push(std::mem::take(&mut current_machine));
                 }
             }
             _ => {}
-        });
+        }
+    }
 
     Ok(claw_machines)
 }
 
-fn find_winning_combination(machine: &ClawMachine) -> Option<((usize, usize), usize)> {
-    let b_cf = machine.b.0 as f64 / machine.b.1 as f64;
-    let a_cf = machine.a.0 as f64 - machine.a.1 as f64 * b_cf;
-    let x = (machine.prize.0 as f64 - machine.prize.1 as f64 * b_cf) / a_cf;
-    let y = (machine.prize.0 as f64 - machine.a.0 as f64 * x) / machine.b.0 as f64;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Infeasible {
+    // The prize is not an integer combination of the button moves at all
+    OutsideLattice,
+    // Branch and bound exhausted all press counts within the limits
+    NoSolution,
+    // The extra buttons allow more press combinations than `SEARCH_LIMIT`
+    TooManyPresses,
+}
+
+// The two buttons with the widest press ranges are solved exactly for every
+// press count of the others, so only those extra counts are enumerated
+const SEARCH_LIMIT: u128 = 10_000_000;
+
+type Presses = (Vec<usize>, usize);
+
+fn find_winning_combination(machine: &ClawMachine) -> Option<Presses> {
+    solve(machine).ok()
+}
+
+fn solve(machine: &ClawMachine) -> Result<Presses, Infeasible> {
+    let prize = (machine.prize.0 as i128, machine.prize.1 as i128);
+    if !lattice_contains(&machine.buttons, prize) {
+        return Err(Infeasible::OutsideLattice);
+    }
+
+    let mut buttons = machine.buttons.clone();
+    if buttons.len() < 2 {
+        // Pad with a button that cannot be pressed to reuse the pair solver
+        buttons.resize(
+            2,
+            Button {
+                name: ' ',
+                delta: (0, 0),
+                cost: 0,
+                max_presses: Some(0),
+            },
+        );
+    }
+
+    // Leave the widest ranges to the pair solver and enumerate the rest
+    let mut order = (0..buttons.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| press_limit(&buttons[idx], prize));
+    let combinations = order[..order.len() - 2]
+        .iter()
+        .map(|&idx| press_limit(&buttons[idx], prize) as u128 + 1)
+        .fold(1u128, u128::saturating_mul);
+    if combinations > SEARCH_LIMIT {
+        return Err(Infeasible::TooManyPresses);
+    }
+
+    let ordered = order
+        .iter()
+        .map(|&idx| buttons[idx].clone())
+        .collect::<Vec<_>>();
+    let mut search = Search {
+        buttons: &ordered,
+        presses: vec![0; ordered.len()],
+        best: None,
+    };
+    search.branch(0, prize, 0);
+
+    let (ordered_presses, cost) = search.best.ok_or(Infeasible::NoSolution)?;
+    let mut presses = vec![0; buttons.len()];
+    for (idx, count) in order.into_iter().zip(ordered_presses) {
+        presses[idx] = count;
+    }
+    presses.truncate(machine.buttons.len());
+    Ok((presses, cost))
+}
 
-    for a in [x.floor() as usize, x.ceil() as usize] {
-        for b in [y.floor() as usize, y.ceil() as usize] {
-            let px = a * machine.a.0 + b * machine.b.0;
-            let py = a * machine.a.1 + b * machine.b.1;
-            if (px, py) != machine.prize {
-                continue;
+// Presses of a button are capped by its own limit and by never overshooting
+// the prize on an axis it moves along.
+fn press_limit(button: &Button, remaining: (i128, i128)) -> i128 {
+    let (dx, dy) = (button.delta.0 as i128, button.delta.1 as i128);
+    if dx == 0 && dy == 0 {
+        return 0;
+    }
+    let mut limit = button.max_presses.unwrap_or(usize::MAX) as i128;
+    if dx > 0 {
+        limit = limit.min(remaining.0 / dx);
+    }
+    if dy > 0 {
+        limit = limit.min(remaining.1 / dy);
+    }
+    limit.max(0)
+}
+
+// Reduces the button moves to a lattice basis in Hermite normal form
+// `(g0, h), (0, g1)` and checks that the prize is an integer combination.
+fn lattice_contains(buttons: &[Button], prize: (i128, i128)) -> bool {
+    let mut basis = (0i128, 0i128);
+    let mut g1 = 0i128;
+
+    for button in buttons {
+        let (bx, by) = (button.delta.0 as i128, button.delta.1 as i128);
+        let (g, x, y) = extended_gcd(basis.0, bx);
+        if g == 0 {
+            g1 = gcd(g1, by);
+            continue;
+        }
+        let reduced_y = (bx / g) * basis.1 - (basis.0 / g) * by;
+        basis = (g, x * basis.1 + y * by);
+        g1 = gcd(g1, reduced_y);
+    }
+
+    let residual_y = if basis.0 == 0 {
+        if prize.0 != 0 {
+            return false;
+        }
+        g1 = gcd(g1, basis.1);
+        prize.1
+    } else {
+        if prize.0 % basis.0 != 0 {
+            return false;
+        }
+        prize.1 - prize.0 / basis.0 * basis.1
+    };
+
+    if g1 == 0 {
+        residual_y == 0
+    } else {
+        residual_y % g1 == 0
+    }
+}
+
+struct Search<'a> {
+    buttons: &'a [Button],
+    presses: Vec<usize>,
+    best: Option<Presses>,
+}
+
+impl Search<'_> {
+    fn branch(&mut self, idx: usize, remaining: (i128, i128), cost: usize) {
+        if let Some((_, best_cost)) = self.best {
+            if cost + self.lower_bound(idx, remaining) >= best_cost {
+                return;
+            }
+        }
+
+        let n = self.buttons.len();
+        if idx == n - 2 {
+            let (a, b) = (&self.buttons[n - 2], &self.buttons[n - 1]);
+            if let Some((pa, pb)) = solve_pair(a, b, remaining) {
+                let total = cost + pa * a.cost + pb * b.cost;
+                if self.best.as_ref().is_none_or(|(_, best)| total < *best) {
+                    self.presses[n - 2] = pa;
+                    self.presses[n - 1] = pb;
+                    self.best = Some((self.presses.clone(), total));
+                }
+            }
+            return;
+        }
+
+        let button = &self.buttons[idx];
+        let (dx, dy) = (button.delta.0 as i128, button.delta.1 as i128);
+        for presses in 0..=press_limit(button, remaining) {
+            self.presses[idx] = presses as usize;
+            self.branch(
+                idx + 1,
+                (remaining.0 - presses * dx, remaining.1 - presses * dy),
+                cost + presses as usize * button.cost,
+            );
+        }
+        self.presses[idx] = 0;
+    }
+
+    // Every press of a button covers at most its own delta on each axis, so
+    // each axis needs at least `remaining * cheapest cost per unit` tokens.
+    fn lower_bound(&self, idx: usize, remaining: (i128, i128)) -> usize {
+        let axis_bound = |target: i128, delta: fn(&Button) -> usize| {
+            if target <= 0 {
+                return 0;
+            }
+            self.buttons[idx..]
+                .iter()
+                .filter(|button| delta(button) > 0)
+                .map(|button| {
+                    let d = delta(button) as i128;
+                    ((target * button.cost as i128 + d - 1) / d) as usize
+                })
+                .min()
+                .unwrap_or(usize::MAX / 2)
+        };
+        axis_bound(remaining.0, |b| b.delta.0).max(axis_bound(remaining.1, |b| b.delta.1))
+    }
+}
+
+fn solve_pair(a: &Button, b: &Button, target: (i128, i128)) -> Option<(usize, usize)> {
+    let (ax, ay) = (a.delta.0 as i128, a.delta.1 as i128);
+    let (bx, by) = (b.delta.0 as i128, b.delta.1 as i128);
+    let (px, py) = target;
+
+    let (pa, pb) = match ax * by - ay * bx {
+        0 => find_collinear_combination(a, b, target)?,
+        det => {
+            let a_det = px * by - py * bx;
+            let b_det = ax * py - ay * px;
+            if a_det % det != 0 || b_det % det != 0 {
+                return None;
             }
-            let cost = a * 3 + b;
-            return Some(((a, b), cost));
+            (a_det / det, b_det / det)
+        }
+    };
+    if pa < 0 || pb < 0 || pa * ax + pb * bx != px || pa * ay + pb * by != py {
+        return None;
+    }
+    let within = |presses: i128, button: &Button| {
+        button.max_presses.is_none_or(|max| presses <= max as i128)
+    };
+    if !within(pa, a) || !within(pb, b) {
+        return None;
+    }
+
+    Some((pa as usize, pb as usize))
+}
+
+// Both buttons move the claw along the same line, so the prize is reachable
+// through a whole line of integer solutions and the cheapest one is picked.
+fn find_collinear_combination(
+    a: &Button,
+    b: &Button,
+    target: (i128, i128),
+) -> Option<(i128, i128)> {
+    let (ax, ay) = (a.delta.0 as i128, a.delta.1 as i128);
+    let (bx, by) = (b.delta.0 as i128, b.delta.1 as i128);
+    let (px, py) = target;
+
+    let (dx, dy) = if (ax, ay) != (0, 0) {
+        (ax, ay)
+    } else {
+        (bx, by)
+    };
+    if dx * py - dy * px != 0 {
+        return None;
+    }
+
+    // Project on the axis the buttons actually move along
+    let (u, v, target) = if dx != 0 { (ax, bx, px) } else { (ay, by, py) };
+    match (u, v) {
+        (0, 0) => (target == 0).then_some((0, 0)),
+        (0, v) => (target % v == 0).then_some((0, target / v)),
+        (u, 0) => (target % u == 0).then_some((target / u, 0)),
+        (u, v) => {
+            let (g, x, y) = extended_gcd(u, v);
+            if target % g != 0 {
+                return None;
+            }
+            // pa = a0 + k * v / g, pb = b0 - k * u / g
+            let (a0, b0) = (x * (target / g), y * (target / g));
+            let (step_a, step_b) = (v / g, u / g);
+            let mut k_min = -a0.div_euclid(step_a);
+            let mut k_max = b0.div_euclid(step_b);
+            if let Some(max) = a.max_presses {
+                k_max = k_max.min((max as i128 - a0).div_euclid(step_a));
+            }
+            if let Some(max) = b.max_presses {
+                k_min = k_min.max(-(max as i128 - b0).div_euclid(step_b));
+            }
+            if k_min > k_max {
+                return None;
+            }
+
+            [k_min, k_max]
+                .into_iter()
+                .map(|k| (a0 + k * step_a, b0 - k * step_b))
+                .min_by_key(|(pa, pb)| pa * a.cost as i128 + pb * b.cost as i128)
         }
     }
-    None
 }
 
 fn main() -> Result<()> {
@@ -104,8 +385,9 @@ fn main() -> Result<()> {
 
     fn part1<R: BufRead>(reader: R) -> Result<usize> {
         let answer = read_claw_machines(reader)?
-            .iter()
-            .filter_map(find_winning_combination)
+            .into_iter()
+            .map(|machine| machine.with_press_limit(100))
+            .filter_map(|machine| find_winning_combination(&machine))
             .map(|(_, cost)| cost)
             .sum();
         Ok(answer)
@@ -150,46 +432,233 @@ fn main() -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{find_winning_combination, read_claw_machines, ClawMachine, TEST};
+    use crate::{
+        find_winning_combination, read_claw_machines, solve, Button, ClawMachine, Infeasible, TEST,
+    };
+    use adv_code_2024::Position;
     use std::io::BufReader;
 
+    fn machine(a: (usize, usize), b: (usize, usize), prize: Position) -> ClawMachine {
+        ClawMachine {
+            buttons: vec![Button::new('A', a), Button::new('B', b)],
+            prize,
+        }
+    }
+
     #[test]
     fn test_read_claw_machines() {
         let machines = read_claw_machines(BufReader::new(TEST.as_bytes())).unwrap();
         assert_eq!(
             machines,
             vec![
-                ClawMachine {
-                    a: (94, 34),
-                    b: (22, 67),
-                    prize: (8400, 5400)
-                },
-                ClawMachine {
-                    a: (26, 66),
-                    b: (67, 21),
-                    prize: (12748, 12176)
-                },
-                ClawMachine {
-                    a: (17, 86),
-                    b: (84, 37),
-                    prize: (7870, 6450)
-                },
-                ClawMachine {
-                    a: (69, 23),
-                    b: (27, 71),
-                    prize: (18641, 10279)
-                },
+                machine((94, 34), (22, 67), (8400, 5400)),
+                machine((26, 66), (67, 21), (12748, 12176)),
+                machine((17, 86), (84, 37), (7870, 6450)),
+                machine((69, 23), (27, 71), (18641, 10279)),
             ]
         );
     }
 
     #[test]
     fn test_find_winning_combination() {
-        let comb = find_winning_combination(&ClawMachine {
-            a: (94, 34),
-            b: (22, 67),
-            prize: (8400, 5400),
-        });
-        assert_eq!(comb, Some(((80, 40), 280)));
+        let comb = find_winning_combination(&machine((94, 34), (22, 67), (8400, 5400)));
+        assert_eq!(comb, Some((vec![80, 40], 280)));
+    }
+
+    #[test]
+    fn test_find_winning_combination_large_offset() {
+        let delta = 10000000000000;
+        let machine = |a, b, prize: Position| machine(a, b, (prize.0 + delta, prize.1 + delta));
+
+        assert_eq!(
+            find_winning_combination(&machine((94, 34), (22, 67), (8400, 5400))),
+            None
+        );
+        assert_eq!(
+            find_winning_combination(&machine((26, 66), (67, 21), (12748, 12176))),
+            Some((vec![118679050709, 103199174542], 459236326669))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((17, 86), (84, 37), (7870, 6450))),
+            None
+        );
+        assert_eq!(
+            find_winning_combination(&machine((69, 23), (27, 71), (18641, 10279))),
+            Some((vec![102851800151, 107526881786], 416082282239))
+        );
+    }
+
+    #[test]
+    fn test_find_winning_combination_collinear() {
+        assert_eq!(
+            find_winning_combination(&machine((1, 1), (2, 2), (10, 10))),
+            Some((vec![0, 5], 5))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((3, 3), (2, 2), (7, 7))),
+            Some((vec![1, 2], 5))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((6, 4), (3, 2), (12, 8))),
+            Some((vec![0, 4], 4))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((2, 4), (3, 6), (13, 26))),
+            Some((vec![2, 3], 9))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((10, 10), (3, 3), (13, 13))),
+            Some((vec![1, 1], 4))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((0, 3), (0, 1), (0, 7))),
+            Some((vec![0, 7], 7))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((0, 0), (2, 3), (4, 6))),
+            Some((vec![0, 2], 2))
+        );
+        assert_eq!(
+            find_winning_combination(&machine((2, 2), (4, 4), (5, 5))),
+            None
+        );
+        assert_eq!(
+            find_winning_combination(&machine((2, 2), (4, 4), (6, 7))),
+            None
+        );
+        assert_eq!(
+            find_winning_combination(&machine((7, 7), (5, 5), (3, 3))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_claw_machines_with_annotations() {
+        let input = r#"Button A: X+2, Y+1, max=10
+Button B: X+1, Y+2, cost=2
+Button C: X+1, Y+1, cost=1, max=3
+Prize: X=10, Y=10"#;
+        let machines = read_claw_machines(BufReader::new(input.as_bytes())).unwrap();
+        assert_eq!(
+            machines,
+            vec![ClawMachine {
+                buttons: vec![
+                    Button {
+                        name: 'A',
+                        delta: (2, 1),
+                        cost: 3,
+                        max_presses: Some(10),
+                    },
+                    Button {
+                        name: 'B',
+                        delta: (1, 2),
+                        cost: 2,
+                        max_presses: None,
+                    },
+                    Button {
+                        name: 'C',
+                        delta: (1, 1),
+                        cost: 1,
+                        max_presses: Some(3),
+                    },
+                ],
+                prize: (10, 10),
+            }]
+        );
+
+        // A = B and 3 * A + C = 10 with at most 3 presses of C
+        assert_eq!(solve(&machines[0]), Ok((vec![3, 3, 1], 16)));
+
+        let input = "Button A: X+2, Y+1, cost=x\nPrize: X=10, Y=10";
+        assert!(read_claw_machines(BufReader::new(input.as_bytes())).is_err());
+    }
+
+    #[test]
+    fn test_solve_press_limits() {
+        let m = machine((94, 34), (22, 67), (8400, 5400));
+        assert_eq!(
+            solve(&m.clone().with_press_limit(100)),
+            Ok((vec![80, 40], 280))
+        );
+        assert_eq!(solve(&m.with_press_limit(50)), Err(Infeasible::NoSolution));
+
+        // Collinear buttons: the cheapest solution needs 6 presses of B
+        let m = machine((1, 1), (2, 2), (12, 12));
+        assert_eq!(solve(&m.clone()), Ok((vec![0, 6], 6)));
+        assert_eq!(solve(&m.with_press_limit(5)), Ok((vec![2, 5], 11)));
+
+        assert_eq!(
+            solve(&machine((2, 2), (4, 4), (5, 5))),
+            Err(Infeasible::OutsideLattice)
+        );
+        assert_eq!(
+            solve(&machine((2, 0), (0, 2), (4, 5))),
+            Err(Infeasible::OutsideLattice)
+        );
+        assert_eq!(
+            solve(&machine((7, 7), (5, 5), (3, 3))),
+            Err(Infeasible::NoSolution)
+        );
+    }
+
+    #[test]
+    fn test_solve_many_buttons() {
+        let buttons = vec![
+            Button::new('A', (3, 0)),
+            Button::new('B', (0, 3)),
+            Button {
+                cost: 5,
+                ..Button::new('C', (2, 2))
+            },
+            Button {
+                cost: 4,
+                ..Button::new('D', (1, 1))
+            },
+        ];
+
+        // A + B cost 4 tokens per (3, 3), C 7.5 and D 12, so the bulk goes to
+        // A and B while D covers what they cannot reach
+        let m = ClawMachine {
+            buttons: buttons.clone(),
+            prize: (9, 9),
+        };
+        assert_eq!(solve(&m), Ok((vec![3, 3, 0, 0], 12)));
+
+        let m = ClawMachine {
+            buttons: buttons.clone(),
+            prize: (10, 10),
+        };
+        assert_eq!(solve(&m), Ok((vec![3, 3, 0, 1], 16)));
+
+        let m = ClawMachine {
+            buttons: buttons[..1].to_vec(),
+            prize: (9, 0),
+        };
+        assert_eq!(solve(&m), Ok((vec![3], 9)));
+
+        let m = ClawMachine {
+            buttons: vec![],
+            prize: (0, 0),
+        };
+        assert_eq!(solve(&m), Ok((vec![], 0)));
+    }
+
+    #[test]
+    fn test_solve_many_buttons_large_offset() {
+        let offset = 10000000000000;
+        let mut m = ClawMachine {
+            buttons: vec![
+                Button::new('A', (3, 0)),
+                Button::new('C', (1, 1)),
+                Button::new('B', (0, 3)),
+            ],
+            prize: (offset, offset),
+        };
+        assert_eq!(solve(&m), Err(Infeasible::TooManyPresses));
+
+        // C is cheaper per (3, 3) than A + B, so it takes all allowed presses
+        m.buttons[1].max_presses = Some(10);
+        let pairs = (offset - 10) / 3;
+        assert_eq!(solve(&m), Ok((vec![pairs, 10, pairs], 4 * pairs + 10)));
     }
 }
@@ -1,21 +1,21 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader, Write};
 use std::result::Result::Ok;
 use itertools::Itertools;
-
-use termion::{clear, cursor, event::Key, input::TermRead, raw::IntoRawMode};
-use std::{thread, time};
+use adv_code_2024::export::{Exporter, Palette};
+use adv_code_2024::viewer::{Simulation, Viewer};
 
 
 const DAY: &str = "14";
 const INPUT_FILE: &str = concatcp!("input/", DAY, ".txt");
 
-const TEST: &str = r#"p=0,4 v=3,-3
+const TEST: &str = r#"area=11,7
+p=0,4 v=3,-3
 p=6,3 v=-1,-3
 p=10,3 v=-1,2
 p=2,0 v=2,-1
@@ -48,27 +48,74 @@ impl Robot {
         Robot { position: (new_x, new_y), ..self }
     }
 
-    fn quadrant(&self, area: (isize, isize)) -> Option<u8> {
-        let mx = area.0 / 2;
-        let my = area.1 / 2;
-        if self.position.0 < mx && self.position.1 < my {
-            Some(0)
-        } else if self.position.0 > mx && self.position.1 < my {
-            Some(1)
-        } else if self.position.0 < mx && self.position.1 > my {
-            Some(2)
-        } else if self.position.0 > mx && self.position.1 > my {
-            Some(3)
-        } else {
-            None
+    // Seconds until the robot is back at its starting position
+    fn period(&self, area: (isize, isize)) -> isize {
+        let axis = |v: isize, size: isize| size / gcd(v.rem_euclid(size) as i128, size as i128) as isize;
+        let (px, py) = (axis(self.velocity.0, area.0), axis(self.velocity.1, area.1));
+        px / gcd(px as i128, py as i128) as isize * py
+    }
+
+    fn section(&self, split: Split, area: (isize, isize)) -> Option<usize> {
+        split.section(self.position, area)
+    }
+}
+
+// Cells that straddle a boundary between two sections, like the middle row of
+// an odd-sized area split in halves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CentreLine {
+    Exclude,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Split {
+    columns: isize,
+    rows: isize,
+    centre: CentreLine,
+}
+
+impl Split {
+    const QUADRANTS: Split = Split { columns: 2, rows: 2, centre: CentreLine::Exclude };
+
+    fn axis_part(&self, x: isize, size: isize, parts: isize) -> Option<isize> {
+        let lower = x * parts / size;
+        let straddles = (lower + 1) * size < (x + 1) * parts;
+        match self.centre {
+            _ if !straddles => Some(lower),
+            CentreLine::Exclude => None,
+            CentreLine::Lower => Some(lower),
+            CentreLine::Upper => Some(lower + 1),
         }
     }
+
+    // Sections are numbered row by row, starting from the top left corner
+    fn section(&self, position: (isize, isize), area: (isize, isize)) -> Option<usize> {
+        let column = self.axis_part(position.0, area.0, self.columns)?;
+        let row = self.axis_part(position.1, area.1, self.rows)?;
+        Some((row * self.columns + column) as usize)
+    }
 }
 
+const DEFAULT_AREA: (isize, isize) = (101, 103);
+
+// Scenarios may start with an `area=W,H` header, otherwise the puzzle area
+// is assumed.
 fn read_input<R: BufRead>(input: R) -> Result<(Vec<Robot>, (isize, isize))> {
-    let robots = input
-        .lines()
-        .map_while(Result::ok)
+    let mut lines = input.lines().map_while(Result::ok).peekable();
+    let area = match lines.next_if(|line| line.starts_with("area=")) {
+        Some(header) => {
+            let (width, height) = header[5..]
+                .split_once(",")
+                .ok_or_else(|| anyhow!("Invalid area header: {}", header))?;
+            (width.trim().parse::<isize>()?, height.trim().parse::<isize>()?)
+        }
+        None => DEFAULT_AREA,
+    };
+    ensure!(area.0 > 0 && area.1 > 0, "Area must not be empty: {:?}", area);
+
+    let robots = lines
         .filter_map(|line| match line.split_once(" ") {
             Some((position, velocity)) => {
                 let position = position[2..].split_once(",")?;
@@ -83,9 +130,31 @@ fn read_input<R: BufRead>(input: R) -> Result<(Vec<Robot>, (isize, isize))> {
         })
         .collect_vec();
 
-    let max_x = robots.iter().map(|robot| robot.position.0).max().unwrap_or_default();
-    let max_y = robots.iter().map(|robot| robot.position.1).max().unwrap_or_default();
-    Ok((robots, (max_x + 1, max_y + 1)))
+    if let Some(robot) = robots.iter().find(|r| {
+        !(0..area.0).contains(&r.position.0) || !(0..area.1).contains(&r.position.1)
+    }) {
+        bail!("Robot {:?} starts outside of area {:?}", robot, area);
+    }
+    Ok((robots, area))
+}
+
+fn safety_factor(robots: &[Robot], area: (isize, isize), seconds: isize, split: Split) -> usize {
+    let mut counts = vec![0; (split.columns * split.rows) as usize];
+    robots
+        .iter()
+        .filter_map(|robot| robot.simulate(seconds, area).section(split, area))
+        .for_each(|section| counts[section] += 1);
+    counts.into_iter().product()
+}
+
+// Cells shared by more than one robot, with the number of robots in each
+fn collisions(robots: &[Robot], area: (isize, isize), seconds: isize) -> BTreeMap<(isize, isize), usize> {
+    let mut cells = BTreeMap::new();
+    for robot in robots {
+        *cells.entry(robot.simulate(seconds, area).position).or_insert(0) += 1;
+    }
+    cells.retain(|_, count| *count > 1);
+    cells
 }
 
 
@@ -103,67 +172,273 @@ fn find_pattern(
     false
 }
 
-fn inspect_manually(robots: Vec<Robot>, area: (isize, isize)) -> Result<()> {
-    let mut seconds = 0;
-
-    let mut stdout = std::io::stdout();
-    let mut stdout = stdout.lock().into_raw_mode()?;
-    let stdin = std::io::stdin();
-    let mut keys = stdin.keys();
-    let mut buffer = String::new();
-
-    loop {
-        write!(
-            stdout,
-            "{}{}",
-            clear::All,          // Clear the screen
-            cursor::Goto(1, 1)   // Move to top-left corner
-        )?;
-
-        let positions = robots
-            .iter()
-            .map(|r| r.simulate(seconds, area).position)
-            .collect::<HashSet<_>>();
-
-        for j in 0..=area.1 {
-            buffer.clear();
-            for i in 0..=area.0 {
-                if positions.contains(&(i, j)) {
-                    buffer.push('#');
-                } else {
-                    buffer.push('.');
-                }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameScore {
+    Variance,
+    Entropy,
+}
+
+impl FrameScore {
+    // Scores robot coordinates along a single axis, lower is more ordered
+    fn axis_score(self, values: &[isize], size: isize) -> f64 {
+        let n = values.len() as f64;
+        match self {
+            FrameScore::Variance => {
+                let mean = values.iter().sum::<isize>() as f64 / n;
+                values.iter().map(|v| (*v as f64 - mean).powi(2)).sum::<f64>() / n
+            }
+            FrameScore::Entropy => {
+                let mut histogram = vec![0usize; size as usize];
+                values.iter().for_each(|v| histogram[*v as usize] += 1);
+                histogram
+                    .into_iter()
+                    .filter(|c| *c > 0)
+                    .map(|c| {
+                        let p = c as f64 / n;
+                        -p * p.ln()
+                    })
+                    .sum()
             }
-            writeln!(stdout, "\r{}", buffer)?; // Use '\r' to ensure correct alignment
         }
-        writeln!(stdout, "\n\rSeconds {}. Press Left/Right to navigate, 'q' to quit.", seconds)?;
-        stdout.flush()?;
+    }
+}
 
-        if let Some(Ok(key)) = keys.next() {
-            match key {
-                Key::Right => {
-                    seconds += 1;
-                }
-                Key::Left => {
-                    seconds -= 1;
-                }
-                Key::PageDown => {
-                    seconds += 100;
-                }
-                Key::PageUp => {
-                    seconds -= 100;
-                }
-                Key::Char('q') => {
-                    break;
+// Robot x coordinates repeat every `area.0` seconds and y coordinates every
+// `area.1` seconds, so each axis is minimized within its own period and the
+// two offsets are combined with CRT. When the sides share a factor the two
+// offsets can contradict each other, and the frames of the full period are
+// ranked by the sum of both axis scores instead.
+fn find_easter_egg(robots: &[Robot], area: (isize, isize), score: FrameScore) -> Result<isize> {
+    if robots.is_empty() {
+        bail!("no robots to look for the easter egg");
+    }
+
+    let axis_scores = |size: isize, axis: fn(&(isize, isize)) -> isize| {
+        (0..size)
+            .map(|t| {
+                let values = robots
+                    .iter()
+                    .map(|r| axis(&r.simulate(t, area).position))
+                    .collect_vec();
+                score.axis_score(&values, size)
+            })
+            .collect_vec()
+    };
+    let best = |scores: &[f64]| {
+        (0..scores.len())
+            .min_by(|a, b| scores[*a].total_cmp(&scores[*b]))
+            .expect("non-empty area") as isize
+    };
+
+    let (xs, ys) = (axis_scores(area.0, |p| p.0), axis_scores(area.1, |p| p.1));
+    let (tx, ty) = (best(&xs), best(&ys));
+    if let Some((t, _)) = crt(&[(tx as i128, area.0 as i128), (ty as i128, area.1 as i128)]) {
+        return Ok(t as isize);
+    }
+
+    let t = (0..system_period(area))
+        .min_by(|a, b| {
+            let frame = |t: isize| xs[(t % area.0) as usize] + ys[(t % area.1) as usize];
+            frame(*a).total_cmp(&frame(*b))
+        })
+        .expect("non-empty area");
+    Ok(t)
+}
+
+fn largest_component(positions: &HashSet<(isize, isize)>) -> usize {
+    let mut visited = HashSet::new();
+    let mut largest = 0;
+
+    for start in positions.iter() {
+        if !visited.insert(*start) {
+            continue;
+        }
+        let mut size = 0;
+        let mut stack = vec![*start];
+        while let Some((x, y)) = stack.pop() {
+            size += 1;
+            for next in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if positions.contains(&next) && visited.insert(next) {
+                    stack.push(next);
                 }
-                _ => {}
             }
         }
+        largest = largest.max(size);
+    }
+
+    largest
+}
+
+// Connected components do not split by axis, so every frame of the full
+// period is scored.
+fn find_most_connected(robots: &[Robot], area: (isize, isize)) -> Option<(isize, usize)> {
+    let period = area.0 / gcd(area.0 as i128, area.1 as i128) as isize * area.1;
+    (0..period)
+        .map(|t| {
+            let positions = robots
+                .iter()
+                .map(|r| r.simulate(t, area).position)
+                .collect::<HashSet<_>>();
+            (t, largest_component(&positions))
+        })
+        .max_by_key(|(t, size)| (*size, -t))
+}
+
+fn system_period(area: (isize, isize)) -> isize {
+    area.0 / gcd(area.0 as i128, area.1 as i128) as isize * area.1
+}
+
+// Number of robots seen in every cell, summed over all frames of a period
+struct Occupancy {
+    frames: isize,
+    counts: Vec<Vec<usize>>,
+}
+
+impl Occupancy {
+    fn accumulate(robots: &[Robot], area: (isize, isize)) -> Self {
+        let frames = system_period(area);
+        let mut counts = vec![vec![0; area.0 as usize]; area.1 as usize];
+        for t in 0..frames {
+            for robot in robots {
+                let (x, y) = robot.simulate(t, area).position;
+                counts[y as usize][x as usize] += 1;
+            }
+        }
+        Occupancy { frames, counts }
+    }
 
-        // Add a slight delay to avoid overwhelming the terminal
-        thread::sleep(time::Duration::from_millis(10));
+    fn write_csv<W: Write>(&self, out: &mut W) -> Result<()> {
+        for row in &self.counts {
+            writeln!(out, "{}", row.iter().join(","))?;
+        }
+        Ok(())
     }
 
+    // Cells are bucketed into ten levels, from '0' for the least visited
+    // to '9' for the most visited.
+    fn render(&self) -> Vec<String> {
+        let cells = self.counts.iter().flatten();
+        let min = cells.clone().min().copied().unwrap_or_default();
+        let max = cells.max().copied().unwrap_or_default();
+        self.counts
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|count| {
+                        let level = (count - min) * 9 / (max - min).max(1);
+                        char::from_digit(level as u32, 10).unwrap()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// The total variance is the sum of per-axis variances, each repeating with
+// the area size along its axis, so only `area.0 + area.1` frames are scored.
+fn clustering_extremes(robots: &[Robot], area: (isize, isize)) -> Option<(isize, isize)> {
+    if robots.is_empty() {
+        return None;
+    }
+
+    let axis_scores = |size: isize, axis: fn(&(isize, isize)) -> isize| {
+        (0..size)
+            .map(|t| {
+                let values = robots
+                    .iter()
+                    .map(|r| axis(&r.simulate(t, area).position))
+                    .collect_vec();
+                FrameScore::Variance.axis_score(&values, size)
+            })
+            .collect_vec()
+    };
+    let xs = axis_scores(area.0, |p| p.0);
+    let ys = axis_scores(area.1, |p| p.1);
+
+    let score = |t: &isize| xs[(t % area.0) as usize] + ys[(t % area.1) as usize];
+    let most = (0..system_period(area)).min_by(|a, b| score(a).total_cmp(&score(b)))?;
+    let least = (0..system_period(area)).max_by(|a, b| score(a).total_cmp(&score(b)))?;
+    Some((most, least))
+}
+
+fn analyze(robots: &[Robot], area: (isize, isize)) -> Result<()> {
+    let periods = robots.iter().map(|r| r.period(area)).counts();
+    println!(
+        "Robot periods: {:?}",
+        periods.into_iter().sorted().collect_vec()
+    );
+    if let Some((most, least)) = clustering_extremes(robots, area) {
+        println!("Most clustered at {} seconds, least clustered at {} seconds", most, least);
+    }
+
+    let occupancy = Occupancy::accumulate(robots, area);
+    std::fs::create_dir_all("export")?;
+    occupancy.write_csv(&mut File::create("export/14-heatmap.csv")?)?;
+    let palette = (0..10).fold(Palette::default(), |palette, level| {
+        let v = (level * 255 / 9) as u8;
+        palette.with(char::from_digit(level, 10).unwrap(), [v, v / 2, 255 - v])
+    });
+    Exporter::new(palette)
+        .with_scale(4)
+        .write_png(&occupancy.render(), &mut File::create("export/14-heatmap.png")?)?;
+    println!("Exported occupancy over {} frames to export/", occupancy.frames);
+    Ok(())
+}
+
+struct RobotSimulation {
+    robots: Vec<Robot>,
+    area: (isize, isize),
+}
+
+impl Simulation for RobotSimulation {
+    type State = HashSet<(isize, isize)>;
+
+    fn state(&self, step: usize) -> Self::State {
+        self.robots
+            .iter()
+            .map(|r| r.simulate(step as isize, self.area).position)
+            .collect()
+    }
+
+    fn render(&self, positions: &Self::State) -> Vec<String> {
+        (0..self.area.1)
+            .map(|j| {
+                (0..self.area.0)
+                    .map(|i| if positions.contains(&(i, j)) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn inspect_manually(robots: Vec<Robot>, area: (isize, isize)) -> Result<()> {
+    let simulation = RobotSimulation { robots, area };
+    let min_component = simulation.robots.len() / 10;
+    let mut viewer = Viewer::new(&simulation)
+        .with_search(move |positions| largest_component(positions) >= min_component);
+    viewer.run()
+}
+
+fn export_frames(robots: Vec<Robot>, area: (isize, isize), seconds: usize) -> Result<()> {
+    let simulation = RobotSimulation { robots, area };
+    let exporter = Exporter::new(
+        Palette::default()
+            .with('.', [16, 24, 32])
+            .with('#', [64, 200, 96]),
+    )
+    .with_scale(4);
+
+    std::fs::create_dir_all("export")?;
+    let frame = simulation.render(&simulation.state(seconds));
+    exporter.write_png(&frame, &mut File::create("export/14-easter-egg.png")?)?;
+    exporter.write_simulation(
+        &simulation,
+        seconds.saturating_sub(10)..=seconds + 10,
+        21,
+        200,
+        &mut File::create("export/14-robots.png")?,
+    )?;
+    println!("Exported frames around {} seconds to export/", seconds);
     Ok(())
 }
 
@@ -174,23 +449,18 @@ fn main() -> Result<()> {
     //region Part 1
     println!("=== Part 1 ===");
 
-    fn part1<R: BufRead>(reader: R) -> Result<isize> {
+    fn part1<R: BufRead>(reader: R) -> Result<usize> {
         let (robots, area) = read_input(reader)?;
         println!("Read {} robots in area {:?}", robots.len(), area);
 
-        let mut counter = HashMap::new();
-        robots
-            .into_iter()
-            .filter_map(|robot| robot.simulate(100, area).quadrant(area))
-            .for_each(|q| {
-                counter.entry(q).and_modify(|c| *c += 1).or_insert(1);
-            });
-
-        println!("Robot counts by quadrant: {:?}", counter);
-        let answer = counter
-            .into_values()
-            .reduce(|a, b| a * b)
-            .unwrap_or_default();
+        let collisions = collisions(&robots, area, 100);
+        println!(
+            "Cells with collisions: {}, most robots in a cell: {}",
+            collisions.len(),
+            collisions.values().max().unwrap_or(&1)
+        );
+
+        let answer = safety_factor(&robots, area, 100, Split::QUADRANTS);
         Ok(answer)
     }
 
@@ -201,12 +471,36 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     //endregion
 
+    // region Sections
+    println!("\n=== Sections ===");
+
+    fn compare_splits<R: BufRead>(reader: R) -> Result<()> {
+        let (robots, area) = read_input(reader)?;
+        for (columns, rows) in [(2, 2), (3, 3), (4, 2)] {
+            for centre in [CentreLine::Exclude, CentreLine::Lower, CentreLine::Upper] {
+                let split = Split { columns, rows, centre };
+                println!(
+                    "{}x{} split, {:?} centre lines: {}",
+                    columns,
+                    rows,
+                    centre,
+                    safety_factor(&robots, area, 100, split)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(compare_splits(input_file)?);
+    // endregion
+
     // region Part 2
     println!("\n=== Part 2 ===");
 
-    fn part2<R: BufRead>(reader: R) -> Result<usize> {
-        let (mut robots, area) = read_input(reader)?;
-        let mut seconds = 0;
+    fn part2<R: BufRead>(reader: R) -> Result<isize> {
+        let (robots, area) = read_input(reader)?;
+        let seconds = find_easter_egg(&robots, area, FrameScore::Variance)?;
 
         let pattern = vec![
                                       (0, 0),
@@ -214,34 +508,78 @@ fn main() -> Result<()> {
                     (-2, 2), (-1, 2), (0, 2), (1, 2), (2, 2),
            (-3, 3), (-2, 3), (-1, 3), (0, 3), (1, 3), (2, 3), (2, 3),
         ];
+        let found = find_pattern(
+            robots.iter().map(|r| r.simulate(seconds, area).position),
+            &pattern,
+        );
+        println!("Tree pattern found at {} seconds: {}", seconds, found);
+        Ok(seconds)
+    }
 
-        loop {
-            seconds += 1;
-            robots.iter_mut().for_each(|r| {
-                *r = r.simulate(1, area)
-            });
-            let found = find_pattern(
-                robots.iter().map(|r| r.position),
-                &pattern,
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    let result = time_snippet!(part2(input_file)?);
+    println!("Result = {}", result);
+    // endregion
+
+    // region Detectors
+    println!("\n=== Detectors ===");
+
+    fn compare_detectors<R: BufRead>(reader: R) -> Result<()> {
+        let (robots, area) = read_input(reader)?;
+        for score in [FrameScore::Variance, FrameScore::Entropy] {
+            let seconds = find_easter_egg(&robots, area, score)?;
+            let positions = robots
+                .iter()
+                .map(|r| r.simulate(seconds, area).position)
+                .collect::<HashSet<_>>();
+            println!(
+                "{:?}: {} seconds, largest component {}",
+                score,
+                seconds,
+                largest_component(&positions)
             );
-            if found {
-                return Ok(seconds);
+        }
+        // Scoring components needs every frame of the period, so it is opt-in
+        if std::env::args().any(|arg| arg == "--connected") {
+            if let Some((seconds, size)) = find_most_connected(&robots, area) {
+                println!("Connected: {} seconds, largest component {}", seconds, size);
             }
         }
+        Ok(())
     }
 
     let input_file = BufReader::new(File::open(INPUT_FILE)?);
-    let result = time_snippet!(part2(input_file)?);
-    println!("Result = {}", result);
+    time_snippet!(compare_detectors(input_file)?);
     // endregion
 
+    if std::env::args().any(|arg| arg == "--inspect") {
+        let (robots, area) = read_input(BufReader::new(File::open(INPUT_FILE)?))?;
+        inspect_manually(robots, area)?;
+    }
+
+    if std::env::args().any(|arg| arg == "--export") {
+        let (robots, area) = read_input(BufReader::new(File::open(INPUT_FILE)?))?;
+        let seconds = find_easter_egg(&robots, area, FrameScore::Variance)?;
+        export_frames(robots, area, seconds as usize)?;
+    }
+
+    if std::env::args().any(|arg| arg == "--analyze") {
+        let (robots, area) = read_input(BufReader::new(File::open(INPUT_FILE)?))?;
+        analyze(&robots, area)?;
+    }
+
     Ok(())
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::{find_pattern, Robot};
+    use crate::{
+        clustering_extremes, collisions, find_easter_egg, find_most_connected, find_pattern,
+        largest_component, read_input, safety_factor, CentreLine, FrameScore, Occupancy, Robot,
+        Split, DEFAULT_AREA, TEST,
+    };
+    use std::collections::{BTreeMap, HashSet};
 
     #[test]
     fn test_simulate_robot() {
@@ -259,14 +597,63 @@ mod tests {
     }
 
     #[test]
-    fn test_quadrant() {
+    fn test_sections() {
         let velocity = (0, 0);
         let area = (11, 7);
+        let split = Split::QUADRANTS;
+
+        assert_eq!(Robot { position: (0, 2), velocity}.section(split, area), Some(0));
+        assert_eq!(Robot { position: (6, 0), velocity}.section(split, area), Some(1));
+        assert_eq!(Robot { position: (3, 5), velocity}.section(split, area), Some(2));
+        assert_eq!(Robot { position: (6, 6), velocity}.section(split, area), Some(3));
+        assert_eq!(Robot { position: (5, 1), velocity}.section(split, area), None);
+
+        // Columns 3 and 6 straddle the boundaries at 3.33 and 6.67
+        let lower = Split { columns: 3, rows: 1, centre: CentreLine::Lower };
+        let upper = Split { columns: 3, rows: 1, centre: CentreLine::Upper };
+        let exclude = Split { centre: CentreLine::Exclude, ..lower };
+        let sections = |split: Split| (0..10).map(|x| split.section((x, 0), (10, 1))).collect::<Vec<_>>();
+        assert_eq!(
+            sections(exclude),
+            vec![Some(0), Some(0), Some(0), None, Some(1), Some(1), None, Some(2), Some(2), Some(2)]
+        );
+        assert_eq!(sections(lower)[3], Some(0));
+        assert_eq!(sections(lower)[6], Some(1));
+        assert_eq!(sections(upper)[3], Some(1));
+        assert_eq!(sections(upper)[6], Some(2));
+
+        // Even sizes have no centre lines at all
+        let halves = Split { columns: 2, rows: 2, centre: CentreLine::Exclude };
+        assert!((0..4).all(|x| halves.section((x, x), (4, 4)).is_some()));
+    }
+
+    #[test]
+    fn test_read_input() {
+        let (robots, area) = read_input(TEST.as_bytes()).unwrap();
+        assert_eq!((robots.len(), area), (12, (11, 7)));
+
+        // No robot starts on the last row, the default area is used
+        let (robots, area) = read_input("p=0,0 v=1,1\np=3,4 v=-1,2".as_bytes()).unwrap();
+        assert_eq!((robots.len(), area), (2, DEFAULT_AREA));
 
-        assert_eq!(Robot { position: (0, 2), velocity}.quadrant(area), Some(0));
-        assert_eq!(Robot { position: (6, 0), velocity}.quadrant(area), Some(1));
-        assert_eq!(Robot { position: (3, 5), velocity}.quadrant(area), Some(2));
-        assert_eq!(Robot { position: (6, 6), velocity}.quadrant(area), Some(3));
+        assert!(read_input("area=11\np=0,0 v=1,1".as_bytes()).is_err());
+        assert!(read_input("area=0,7".as_bytes()).is_err());
+        assert!(read_input("area=11,7\np=11,0 v=1,1".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_safety_factor_and_collisions() {
+        let (robots, area) = read_input(TEST.as_bytes()).unwrap();
+        assert_eq!(safety_factor(&robots, area, 100, Split::QUADRANTS), 12);
+        let split = Split { columns: 1, rows: 1, centre: CentreLine::Exclude };
+        assert_eq!(safety_factor(&robots, area, 100, split), 12);
+        let split = Split { columns: 3, rows: 3, centre: CentreLine::Exclude };
+        assert_eq!(safety_factor(&robots, area, 100, split), 0);
+
+        assert_eq!(
+            collisions(&robots, area, 100),
+            BTreeMap::from([((4, 5), 2), ((6, 0), 2)])
+        );
     }
 
     #[test]
@@ -285,4 +672,83 @@ mod tests {
         );
         assert_eq!(true, found);
     }
+
+    // Places the robots on a filled square at `seconds` and scatters their
+    // velocities so that every other frame looks like noise.
+    fn hidden_square(area: (isize, isize), seconds: isize) -> Vec<Robot> {
+        let mut seed = 17isize;
+        let mut next = |m: isize| {
+            seed = (seed * 1103515245 + 12345) % 2147483648;
+            seed % m
+        };
+
+        (0..5)
+            .flat_map(|x| (0..5).map(move |y| (x + 10, y + 6)))
+            .map(|target| {
+                let velocity = (next(area.0) - area.0 / 2, next(area.1) - area.1 / 2);
+                let robot = Robot { position: target, velocity };
+                robot.simulate(-seconds, area)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_easter_egg() {
+        let area = (31, 23);
+        let robots = hidden_square(area, 500);
+        assert_eq!(robots[0].simulate(500, area).position, (10, 6));
+
+        assert_eq!(find_easter_egg(&robots, area, FrameScore::Variance).unwrap(), 500);
+        assert_eq!(find_easter_egg(&robots, area, FrameScore::Entropy).unwrap(), 500);
+        assert_eq!(find_most_connected(&robots, area), Some((500, 25)));
+        assert!(find_easter_egg(&[], area, FrameScore::Variance).is_err());
+
+        // Sides sharing a factor still combine when the offsets agree
+        let area = (30, 24);
+        let robots = hidden_square(area, 100);
+        assert_eq!(find_easter_egg(&robots, area, FrameScore::Variance).unwrap(), 100);
+
+        // x lines up at even seconds and y at odd ones, so no frame has both
+        // and the first frame closest on both axes wins
+        let area = (4, 6);
+        let robots = [
+            Robot { position: (0, 0), velocity: (0, 0) },
+            Robot { position: (0, 3), velocity: (1, 1) },
+        ];
+        assert_eq!(find_easter_egg(&robots, area, FrameScore::Variance).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_periods_and_occupancy() {
+        let area = (4, 6);
+        let robot = |velocity| Robot { position: (0, 0), velocity };
+        assert_eq!(robot((1, 1)).period(area), 12);
+        assert_eq!(robot((2, 3)).period(area), 2);
+        assert_eq!(robot((0, -4)).period(area), 3);
+        assert_eq!(robot((0, 0)).period(area), 1);
+
+        let occupancy = Occupancy::accumulate(&[robot((0, 0)), robot((2, 3))], area);
+        assert_eq!(occupancy.frames, 12);
+        assert_eq!(occupancy.counts[0][0], 18);
+        assert_eq!(occupancy.counts[3][2], 6);
+        assert_eq!(occupancy.counts.iter().flatten().sum::<usize>(), 24);
+        assert_eq!(occupancy.render()[0], "9000");
+        assert_eq!(occupancy.render()[3], "0030");
+
+        let mut csv = vec![];
+        occupancy.write_csv(&mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().starts_with("18,0,0,0\n0,0,0,0\n"));
+
+        let area = (31, 23);
+        let robots = hidden_square(area, 500);
+        assert_eq!(clustering_extremes(&robots, area).map(|(most, _)| most), Some(500));
+        assert_eq!(clustering_extremes(&[], area), None);
+    }
+
+    #[test]
+    fn test_largest_component() {
+        let positions = HashSet::from([(0, 0), (0, 1), (1, 1), (5, 5), (5, 6), (7, 7)]);
+        assert_eq!(largest_component(&positions), 3);
+        assert_eq!(largest_component(&HashSet::new()), 0);
+    }
 }
@@ -5,6 +5,7 @@ use const_format::concatcp;
 use itertools::Itertools;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::RangeInclusive;
 use std::result::Result::Ok;
 
 const DAY: &str = "2";
@@ -19,39 +20,133 @@ const TEST: &str = "\
 1 3 6 7 9
 ";
 
-fn main() -> Result<()> {
-    start_day(DAY);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Increasing,
+    Decreasing,
+    Either,
+}
 
-    //region Part 1
-    println!("=== Part 1 ===");
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tolerance {
+    steps: RangeInclusive<i32>,
+    direction: Direction,
+    max_removals: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            steps: 1..=3,
+            direction: Direction::Either,
+            max_removals: 0,
+        }
+    }
+}
+
+impl Tolerance {
+    fn with_removals(max_removals: usize) -> Self {
+        Self {
+            max_removals,
+            ..Self::default()
+        }
+    }
 
-    fn check_report_orig(record: &[i32]) -> bool {
-        let is_desc = record[0] > record[1];
+    fn is_safe(&self, report: &[i32]) -> bool {
+        self.removals(report).is_some()
+    }
 
-        for i in 0..(record.len() - 1) {
-            let a = record[i];
-            let b = record[i + 1];
-            if is_desc && a < b || !is_desc && a > b {
-                return false;
+    // Indices of the fewest levels to remove to make the report safe, or
+    // `None` when more than `max_removals` would be needed.
+    fn removals(&self, report: &[i32]) -> Option<Vec<usize>> {
+        if report.is_empty() {
+            return Some(vec![]);
+        }
+        match self.direction {
+            Direction::Either => [Direction::Increasing, Direction::Decreasing]
+                .into_iter()
+                .filter_map(|direction| self.removals_in(report, direction))
+                .min_by_key(|removed| removed.len()),
+            direction => self.removals_in(report, direction),
+        }
+    }
+
+    // `removed[i][r]` holds the level kept before level `i` when `i` is kept
+    // after `r` removals, so each level looks back at most `max_removals + 1`
+    // levels and the whole pass is linear for a fixed tolerance.
+    fn removals_in(&self, report: &[i32], direction: Direction) -> Option<Vec<usize>> {
+        let n = report.len();
+        let k = self.max_removals;
+        let fits = |a: i32, b: i32| {
+            let step = match direction {
+                Direction::Decreasing => a - b,
+                _ => b - a,
+            };
+            self.steps.contains(&step)
+        };
+
+        // `None` marks a level that starts the kept sequence
+        let mut previous = vec![vec![None; k + 1]; n];
+        let mut reachable = vec![vec![false; k + 1]; n];
+        for i in 0..n {
+            if i <= k {
+                reachable[i][i] = true;
+            }
+            for skipped in 0..i.min(k + 1) {
+                let j = i - skipped - 1;
+                if !fits(report[j], report[i]) {
+                    continue;
+                }
+                for used in 0..=k - skipped {
+                    if reachable[j][used] && !reachable[i][used + skipped] {
+                        reachable[i][used + skipped] = true;
+                        previous[i][used + skipped] = Some(j);
+                    }
+                }
             }
+        }
 
-            let diff = (a - b).abs();
-            if !(1..=3).contains(&diff) {
-                return false;
+        let (mut last, mut used) = (0..n.min(k + 1))
+            .map(|trailing| n - 1 - trailing)
+            .flat_map(|i| (0..=k - (n - 1 - i)).map(move |used| (i, used)))
+            .filter(|(i, used)| reachable[*i][*used])
+            .min_by_key(|(i, used)| used + n - 1 - i)?;
+
+        let mut kept = vec![false; n];
+        loop {
+            kept[last] = true;
+            match previous[last][used] {
+                Some(j) => {
+                    used -= last - j - 1;
+                    last = j;
+                }
+                None => break,
             }
         }
-        true
+        Some((0..n).filter(|i| !kept[*i]).collect())
     }
+}
+
+fn read_reports<R: BufRead>(reader: R) -> Vec<Vec<i32>> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.split(" ").flat_map(|s| s.parse::<i32>()).collect_vec())
+        .collect()
+}
+
+fn main() -> Result<()> {
+    start_day(DAY);
+
+    //region Part 1
+    println!("=== Part 1 ===");
 
     fn part1<R: BufRead>(reader: R) -> Result<usize> {
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .map(|line| {
-                let report = line.split(" ").flat_map(|s| s.parse::<i32>()).collect_vec();
-                check_report_orig(&report) as usize
-            })
-            .sum::<usize>();
+        let tolerance = Tolerance::default();
+        let answer = read_reports(reader)
+            .iter()
+            .filter(|report| tolerance.is_safe(report))
+            .count();
         Ok(answer)
     }
 
@@ -66,26 +161,11 @@ fn main() -> Result<()> {
     println!("\n=== Part 2 ===");
 
     fn part2<R: BufRead>(reader: R) -> Result<usize> {
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .map(|line| {
-                let report = line.split(" ").flat_map(|s| s.parse::<i32>()).collect_vec();
-
-                if check_report_orig(&report) {
-                    return 1;
-                }
-                for i in 0..report.len() {
-                    let mut changed_report = report.clone();
-                    changed_report.remove(i);
-                    // println!("{}: {:?}", i, changed_report);
-                    if check_report_orig(&changed_report) {
-                        return 1;
-                    }
-                }
-                0
-            })
-            .sum::<usize>();
+        let tolerance = Tolerance::with_removals(1);
+        let answer = read_reports(reader)
+            .iter()
+            .filter(|report| tolerance.is_safe(report))
+            .count();
         Ok(answer)
     }
 
@@ -96,5 +176,140 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     //endregion
 
+    //region Tolerances
+    println!("\n=== Tolerances ===");
+
+    fn compare_tolerances<R: BufRead>(reader: R) -> Result<()> {
+        let reports = read_reports(reader);
+        for max_removals in 0..=3 {
+            for direction in [
+                Direction::Increasing,
+                Direction::Decreasing,
+                Direction::Either,
+            ] {
+                let tolerance = Tolerance {
+                    direction,
+                    ..Tolerance::with_removals(max_removals)
+                };
+                let safe = reports.iter().filter(|r| tolerance.is_safe(r)).count();
+                println!("{} removals, {:?}: {} safe", max_removals, direction, safe);
+            }
+        }
+
+        let tolerance = Tolerance {
+            steps: 1..=5,
+            ..Tolerance::with_removals(1)
+        };
+        let safe = reports.iter().filter(|r| tolerance.is_safe(r)).count();
+        println!("1 removal, steps 1..=5: {} safe", safe);
+        Ok(())
+    }
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(compare_tolerances(input_file)?);
+    //endregion
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{read_reports, Direction, Tolerance, TEST};
+    use itertools::Itertools;
+
+    #[test]
+    fn test_removals() {
+        let reports = read_reports(TEST.as_bytes());
+        let tolerance = Tolerance::with_removals(1);
+        let removals = reports
+            .iter()
+            .map(|r| tolerance.removals(r))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            removals,
+            vec![
+                Some(vec![]),
+                None,
+                None,
+                Some(vec![1]),
+                Some(vec![2]),
+                Some(vec![]),
+            ]
+        );
+
+        let tolerance = Tolerance::with_removals(2);
+        assert_eq!(tolerance.removals(&[1, 9, 2, 9, 3]), Some(vec![1, 3]));
+        assert_eq!(tolerance.removals(&[9, 8, 1, 2, 3]), Some(vec![0, 1]));
+        assert_eq!(tolerance.removals(&[1, 2, 3, 9, 9]), Some(vec![3, 4]));
+        assert_eq!(tolerance.removals(&[1, 9, 9, 9, 2]), None);
+        assert_eq!(tolerance.removals(&[5, 5]), Some(vec![0]));
+        assert_eq!(tolerance.removals(&[]), Some(vec![]));
+    }
+
+    #[test]
+    fn test_direction_and_steps() {
+        let increasing = Tolerance {
+            direction: Direction::Increasing,
+            ..Tolerance::default()
+        };
+        assert!(increasing.is_safe(&[1, 2, 4, 7]));
+        assert!(!increasing.is_safe(&[7, 4, 2, 1]));
+
+        let decreasing = Tolerance {
+            direction: Direction::Decreasing,
+            ..Tolerance::with_removals(1)
+        };
+        assert_eq!(decreasing.removals(&[7, 4, 5, 1]), Some(vec![2]));
+
+        let wide = Tolerance {
+            steps: 0..=10,
+            ..Tolerance::default()
+        };
+        assert!(wide.is_safe(&[1, 1, 11, 11]));
+        assert!(!Tolerance::default().is_safe(&[1, 1, 11, 11]));
+    }
+
+    #[test]
+    fn test_removals_against_brute_force() {
+        let safe = |report: &[i32]| {
+            let steps = report
+                .iter()
+                .tuple_windows()
+                .map(|(a, b)| b - a)
+                .collect_vec();
+            steps.iter().all(|s| (1..=3).contains(s)) || steps.iter().all(|s| (-3..=-1).contains(s))
+        };
+
+        let mut seed = 7u64;
+        for _ in 0..2000 {
+            let report = (0..7)
+                .map(|_| {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (seed >> 33) as i32 % 8
+                })
+                .collect_vec();
+            for k in 0..=2 {
+                let fewest = (0..=k).find(|r| {
+                    (0..report.len()).combinations(*r).any(|removed| {
+                        let rest = (0..report.len())
+                            .filter(|i| !removed.contains(i))
+                            .map(|i| report[i])
+                            .collect_vec();
+                        safe(&rest)
+                    })
+                });
+                let removals = Tolerance::with_removals(k).removals(&report);
+                assert_eq!(removals.as_ref().map(|r| r.len()), fewest, "{:?}", report);
+                if let Some(removed) = removals {
+                    let rest = (0..report.len())
+                        .filter(|i| !removed.contains(i))
+                        .map(|i| report[i])
+                        .collect_vec();
+                    assert!(safe(&rest), "{:?} without {:?}", report, removed);
+                }
+            }
+        }
+    }
+}
@@ -2,9 +2,10 @@ use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
-use regex::Regex;
+use itertools::Itertools;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Bytes, Read};
 use std::result::Result::Ok;
 
 const DAY: &str = "3";
@@ -12,6 +13,286 @@ const INPUT_FILE: &str = concatcp!("input/", DAY, ".txt");
 
 const TEST: &str = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5)";
 const TEST2: &str = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+const TEST3: &str = "mul(2,4)add(1,1)don't()neg(9)do()\nneg(3)mul(10,\n10)reset()add(7,35)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Call {
+    name: String,
+    args: Vec<u64>,
+    offset: usize,
+}
+
+impl std::fmt::Display for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.name, self.args.iter().join(","))
+    }
+}
+
+// Finds `name(arg,...)` calls in a stream of bytes. A call is recognized when
+// a registered name ends right before the opening parenthesis, so `xmul(2,4)`
+// is still a `mul` call.
+struct Tokenizer<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    names: Vec<String>,
+    max_digits: usize,
+    join_lines: bool,
+    offset: usize,
+    ident: VecDeque<(usize, u8)>,
+    replay: Option<(usize, u8)>,
+}
+
+impl<R: Read> Tokenizer<R> {
+    fn next_byte(&mut self) -> Option<Result<(usize, u8)>> {
+        if let Some(byte) = self.replay.take() {
+            return Some(Ok(byte));
+        }
+        loop {
+            let byte = match self.bytes.next()? {
+                Ok(byte) => byte,
+                Err(error) => return Some(Err(error.into())),
+            };
+            let offset = self.offset;
+            self.offset += 1;
+            if self.join_lines && matches!(byte, b'\n' | b'\r') {
+                continue;
+            }
+            return Some(Ok((offset, byte)));
+        }
+    }
+
+    // Arguments are never part of a name, so a byte that breaks the argument
+    // list only has to be replayed to look for the next call. Numbers too
+    // large for `u64` make the call malformed like any other bad argument.
+    fn read_args(&mut self) -> Result<Option<Vec<u64>>> {
+        let mut args = vec![];
+        let mut digits = String::new();
+        while let Some(next) = self.next_byte() {
+            let (offset, byte) = next?;
+            match byte {
+                b'0'..=b'9' if digits.len() < self.max_digits => digits.push(byte as char),
+                b',' if !digits.is_empty() => match std::mem::take(&mut digits).parse() {
+                    Ok(arg) => args.push(arg),
+                    Err(_) => return Ok(None),
+                },
+                b')' if !digits.is_empty() || args.is_empty() => {
+                    if !digits.is_empty() {
+                        let Ok(arg) = digits.parse() else {
+                            return Ok(None);
+                        };
+                        args.push(arg);
+                    }
+                    return Ok(Some(args));
+                }
+                _ => {
+                    self.replay = Some((offset, byte));
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for Tokenizer<R> {
+    type Item = Result<Call>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let longest = self.names.iter().map(|n| n.len()).max().unwrap_or_default();
+        loop {
+            let (offset, byte) = match self.next_byte()? {
+                Ok(next) => next,
+                Err(error) => return Some(Err(error)),
+            };
+            if byte.is_ascii_alphabetic() || byte == b'\'' || byte == b'_' {
+                self.ident.push_back((offset, byte));
+                if self.ident.len() > longest {
+                    self.ident.pop_front();
+                }
+                continue;
+            }
+
+            let ident = std::mem::take(&mut self.ident);
+            if byte != b'(' {
+                continue;
+            }
+            let Some(name) = self
+                .names
+                .iter()
+                .filter(|name| {
+                    name.len() <= ident.len()
+                        && ident
+                            .iter()
+                            .skip(ident.len() - name.len())
+                            .map(|(_, b)| *b)
+                            .eq(name.bytes())
+                })
+                .max_by_key(|name| name.len())
+                .cloned()
+            else {
+                continue;
+            };
+
+            match self.read_args() {
+                Ok(Some(args)) => {
+                    let offset = ident[ident.len() - name.len()].0;
+                    return Some(Ok(Call { name, args, offset }));
+                }
+                Ok(None) => continue,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Machine {
+    enabled: bool,
+    total: i64,
+}
+
+impl Machine {
+    // Sums and products of `u64` arguments fit in `u128`, only the total overflows
+    fn add(&mut self, value: u128) -> Result<()> {
+        self.total = i64::try_from(value)
+            .ok()
+            .and_then(|value| self.total.checked_add(value))
+            .ok_or_else(|| anyhow!("total {} overflows when adding {}", self.total, value))?;
+        Ok(())
+    }
+
+    fn sub(&mut self, value: u128) -> Result<()> {
+        self.total = i64::try_from(value)
+            .ok()
+            .and_then(|value| self.total.checked_sub(value))
+            .ok_or_else(|| anyhow!("total {} overflows when subtracting {}", self.total, value))?;
+        Ok(())
+    }
+}
+
+type Handler = Box<dyn Fn(&mut Machine, &[u64]) -> Result<()>>;
+
+struct Instruction {
+    arity: usize,
+    // Control instructions run even while the machine is disabled
+    control: bool,
+    handler: Handler,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Execution {
+    total: i64,
+    executed: Vec<Call>,
+}
+
+struct Interpreter {
+    instructions: HashMap<String, Instruction>,
+    max_digits: usize,
+    join_lines: bool,
+}
+
+impl Interpreter {
+    fn new() -> Self {
+        Self {
+            instructions: HashMap::new(),
+            max_digits: 3,
+            join_lines: true,
+        }
+    }
+
+    fn standard() -> Self {
+        Self::new()
+            .register("mul", 2, |m, args| {
+                m.add(u128::from(args[0]) * u128::from(args[1]))
+            })
+            .register_control("do", 0, |m, _| {
+                m.enabled = true;
+                Ok(())
+            })
+            .register_control("don't", 0, |m, _| {
+                m.enabled = false;
+                Ok(())
+            })
+    }
+
+    fn register<F: Fn(&mut Machine, &[u64]) -> Result<()> + 'static>(
+        self,
+        name: &str,
+        arity: usize,
+        handler: F,
+    ) -> Self {
+        self.add_instruction(name, arity, false, Box::new(handler))
+    }
+
+    fn register_control<F: Fn(&mut Machine, &[u64]) -> Result<()> + 'static>(
+        self,
+        name: &str,
+        arity: usize,
+        handler: F,
+    ) -> Self {
+        self.add_instruction(name, arity, true, Box::new(handler))
+    }
+
+    fn add_instruction(
+        mut self,
+        name: &str,
+        arity: usize,
+        control: bool,
+        handler: Handler,
+    ) -> Self {
+        let instruction = Instruction {
+            arity,
+            control,
+            handler,
+        };
+        self.instructions.insert(name.to_string(), instruction);
+        self
+    }
+
+    fn with_max_digits(mut self, max_digits: usize) -> Self {
+        self.max_digits = max_digits;
+        self
+    }
+
+    // Line breaks are ignored by default so that calls wrapped over several
+    // lines still count; this keeps them as separators instead
+    fn with_line_breaks(mut self) -> Self {
+        self.join_lines = false;
+        self
+    }
+
+    fn run<R: Read>(&self, reader: R) -> Result<Execution> {
+        let tokenizer = Tokenizer {
+            bytes: BufReader::new(reader).bytes(),
+            names: self.instructions.keys().cloned().collect(),
+            max_digits: self.max_digits,
+            join_lines: self.join_lines,
+            offset: 0,
+            ident: VecDeque::new(),
+            replay: None,
+        };
+
+        let mut machine = Machine {
+            enabled: true,
+            total: 0,
+        };
+        let mut executed = vec![];
+        for call in tokenizer {
+            let call = call?;
+            let instruction = &self.instructions[&call.name];
+            if instruction.arity != call.args.len() || !(machine.enabled || instruction.control) {
+                continue;
+            }
+            (instruction.handler)(&mut machine, &call.args)
+                .with_context(|| format!("executing {} at {}", call, call.offset))?;
+            executed.push(call);
+        }
+
+        Ok(Execution {
+            total: machine.total,
+            executed,
+        })
+    }
+}
 
 fn main() -> Result<()> {
     start_day(DAY);
@@ -19,23 +300,11 @@ fn main() -> Result<()> {
     //region Part 1
     println!("=== Part 1 ===");
 
-    fn part1<R: BufRead>(reader: R) -> Result<usize> {
-        let re = Regex::new(r"mul\((\d{1,3}),(\d{1,3})\)").expect("regex compilation");
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .map(|line| {
-                re.captures_iter(&line)
-                    .filter_map(|caps| {
-                        let (_, [a, b]) = caps.extract();
-                        match (a.parse::<usize>(), b.parse::<usize>()) {
-                            (Ok(a), Ok(b)) => Some(a * b),
-                            _ => None,
-                        }
-                    })
-                    .sum::<usize>()
-            })
-            .sum::<usize>();
+    fn part1<R: BufRead>(reader: R) -> Result<i64> {
+        let interpreter = Interpreter::new().register("mul", 2, |m, args| {
+            m.add(u128::from(args[0]) * u128::from(args[1]))
+        });
+        let answer = interpreter.run(reader)?.total;
         Ok(answer)
     }
 
@@ -49,35 +318,8 @@ fn main() -> Result<()> {
     //region Part 2
     println!("\n=== Part 2 ===");
 
-    fn part2<R: BufRead>(reader: R) -> Result<usize> {
-        let re = Regex::new(r"(?<do>do\(\))|(?<dont>don't\(\))|mul\((?<a>\d{1,3}),(?<b>\d{1,3})\)")
-            .expect("regex compilation");
-
-        let mut enabled = true;
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .map(|line| {
-                re.captures_iter(&line)
-                    .filter_map(|caps| {
-                        if caps.name("do").is_some() {
-                            enabled = true;
-                            None
-                        } else if caps.name("dont").is_some() {
-                            enabled = false;
-                            None
-                        } else if let (Some(a), Some(b)) = (caps.name("a"), caps.name("b")) {
-                            match (a.as_str().parse::<usize>(), b.as_str().parse::<usize>()) {
-                                (Ok(a), Ok(b)) if enabled => Some(a * b),
-                                _ => None,
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .sum::<usize>()
-            })
-            .sum::<usize>();
+    fn part2<R: BufRead>(reader: R) -> Result<i64> {
+        let answer = Interpreter::standard().run(reader)?.total;
         Ok(answer)
     }
 
@@ -88,5 +330,149 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    //region Extensions
+    println!("\n=== Extensions ===");
+
+    let interpreter = Interpreter::standard()
+        .register("add", 2, |m, args| {
+            m.add(u128::from(args[0]) + u128::from(args[1]))
+        })
+        .register("neg", 1, |m, args| m.sub(u128::from(args[0])))
+        .register("reset", 0, |m, _| {
+            m.total = 0;
+            Ok(())
+        });
+    let execution = interpreter.run(TEST3.as_bytes())?;
+    for call in execution.executed.iter() {
+        println!("{:>3}: {}", call.offset, call);
+    }
+    println!("Result = {}", execution.total);
+
+    let separated = Interpreter::standard()
+        .with_line_breaks()
+        .run(BufReader::new(File::open(INPUT_FILE)?))?;
+    println!("Result with line breaks kept = {}", separated.total);
+
+    let longer = Interpreter::standard()
+        .with_max_digits(4)
+        .run(BufReader::new(File::open(INPUT_FILE)?))?;
+    println!("Result with up to 4 digits = {}", longer.total);
+    // endregion
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Interpreter, TEST, TEST2, TEST3};
+
+    fn calls(interpreter: &Interpreter, input: &str) -> Vec<String> {
+        let execution = interpreter.run(input.as_bytes()).unwrap();
+        execution
+            .executed
+            .iter()
+            .map(|call| format!("{}@{}", call, call.offset))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        let interpreter = Interpreter::standard();
+        assert_eq!(
+            calls(&interpreter, TEST),
+            vec!["mul(2,4)@1", "mul(5,5)@29", "mul(11,8)@53", "mul(8,5)@62"]
+        );
+        assert_eq!(
+            calls(&interpreter, TEST2),
+            vec!["mul(2,4)@1", "don't()@20", "do()@59", "mul(8,5)@64"]
+        );
+
+        // A broken call does not hide the one right after it
+        assert_eq!(calls(&interpreter, "mul(2,mul(3,4)"), vec!["mul(3,4)@6"]);
+        assert_eq!(
+            calls(&interpreter, "mul(2,)mul(1234,1)mul(,3)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            calls(&interpreter, "mul(2)mul(1,2,3)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(calls(&interpreter, "mulmul(1,2)"), vec!["mul(1,2)@3"]);
+    }
+
+    #[test]
+    fn test_line_joining() {
+        let input = "mul(12,\n34)do\n()mu\r\nl(2,3)";
+        assert_eq!(
+            calls(&Interpreter::standard(), input),
+            vec!["mul(12,34)@0", "do()@11", "mul(2,3)@16"]
+        );
+        assert_eq!(
+            calls(&Interpreter::standard().with_line_breaks(), input),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            calls(&Interpreter::standard().with_max_digits(4), "mul(1234,1)"),
+            vec!["mul(1234,1)@0"]
+        );
+
+        // Numbers past `u64` are skipped like any other malformed call
+        let interpreter = Interpreter::standard().with_max_digits(30);
+        assert_eq!(
+            calls(&interpreter, "mul(99999999999999999999,2)mul(3,4)"),
+            vec!["mul(3,4)@27"]
+        );
+        assert_eq!(
+            calls(&interpreter, "mul(2,99999999999999999999)mul(3,4)"),
+            vec!["mul(3,4)@27"]
+        );
+    }
+
+    #[test]
+    fn test_registered_instructions() {
+        let interpreter = Interpreter::standard()
+            .register("add", 2, |m, args| {
+                m.add(u128::from(args[0]) + u128::from(args[1]))
+            })
+            .register("neg", 1, |m, args| m.sub(u128::from(args[0])))
+            .register("reset", 0, |m, _| {
+                m.total = 0;
+                Ok(())
+            });
+
+        let execution = interpreter.run(TEST3.as_bytes()).unwrap();
+        assert_eq!(execution.total, 42);
+        // The wrapped `mul(10,\n10)` runs too, right before `reset()`
+        assert_eq!(execution.executed.len(), 8);
+
+        let execution = interpreter.run("add(1,2)neg(4)".as_bytes()).unwrap();
+        assert_eq!(execution.total, -1);
+        let execution = interpreter
+            .run("undon't()add(1,2)reset(1)".as_bytes())
+            .unwrap();
+        assert_eq!(execution.total, 0);
+
+        // Overflowing the total is an error rather than a wrapped value
+        let interpreter = interpreter.with_max_digits(20);
+        let error = interpreter
+            .run("mul(4294967296,4294967296)".as_bytes())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "executing mul(4294967296,4294967296) at 0"
+        );
+        assert!(interpreter
+            .run("mul(18446744073709551615,18446744073709551615)".as_bytes())
+            .is_err());
+        assert!(interpreter
+            .run("add(9223372036854775807,1)".as_bytes())
+            .is_err());
+        assert!(interpreter
+            .run("neg(9223372036854775807)neg(2)".as_bytes())
+            .is_err());
+        let execution = interpreter
+            .run("neg(9223372036854775807)neg(1)".as_bytes())
+            .unwrap();
+        assert_eq!(execution.total, i64::MIN);
+    }
+}
@@ -41,6 +41,223 @@ const TEST: &str = r#"47|53
 97,13,75,29,47
 "#;
 
+// Rule `before|after` broken by `after` showing up first in an update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Violation {
+    before: (usize, usize),
+    after: (usize, usize),
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}|{} violated: {} at index {} precedes {} at index {}",
+            self.before.0, self.after.0, self.after.0, self.after.1, self.before.0, self.before.1
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ValidationReport {
+    violations: Vec<Violation>,
+    corrected: Vec<usize>,
+    // Whether the rules allow no other order of the pages
+    unique: bool,
+    // Whether the middle page is the same in every order the rules allow
+    middle_determined: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct PrecedenceGraph {
+    successors: HashMap<usize, HashSet<usize>>,
+}
+
+impl PrecedenceGraph {
+    fn add_rule(&mut self, before: usize, after: usize) {
+        self.successors.entry(before).or_default().insert(after);
+    }
+
+    fn precedes(&self, a: usize, b: usize) -> bool {
+        self.successors.get(&a).is_some_and(|s| s.contains(&b))
+    }
+
+    fn is_ordered(&self, update: &[usize]) -> bool {
+        self.violations(update).is_empty()
+    }
+
+    fn violations(&self, update: &[usize]) -> Vec<Violation> {
+        update
+            .iter()
+            .enumerate()
+            .tuple_combinations()
+            .filter(|((_, a), (_, b))| self.precedes(**b, **a))
+            .map(|((i, a), (j, b))| Violation {
+                before: (*b, j),
+                after: (*a, i),
+            })
+            .collect()
+    }
+
+    fn validate(&self, update: &[usize]) -> Result<ValidationReport> {
+        let corrected = self.sort(update)?;
+        // A topological order is the only one exactly when every two
+        // neighbouring pages are bound by a rule.
+        let unique = corrected
+            .iter()
+            .tuple_windows()
+            .all(|(a, b)| self.precedes(*a, *b));
+
+        // The middle page is fixed when exactly the pages before it precede
+        // it and exactly the pages after it follow it.
+        let middle = corrected.len() / 2;
+        let pages = corrected.iter().copied().collect::<HashSet<_>>();
+        let middle_determined = self.reachable(corrected[middle], &pages).len()
+            == corrected.len() - middle - 1
+            && corrected[..middle]
+                .iter()
+                .all(|page| self.reachable(*page, &pages).contains(&corrected[middle]));
+
+        Ok(ValidationReport {
+            violations: self.violations(update),
+            corrected,
+            unique,
+            middle_determined,
+        })
+    }
+
+    // Pages that must come after `page`, following rules within `pages` only
+    fn reachable(&self, page: usize, pages: &HashSet<usize>) -> HashSet<usize> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![page];
+        while let Some(page) = stack.pop() {
+            for next in self.successors.get(&page).into_iter().flatten() {
+                if pages.contains(next) && visited.insert(*next) {
+                    stack.push(*next);
+                }
+            }
+        }
+        visited
+    }
+
+    // The rules form a total order when all mentioned pages sort without a
+    // cycle into the only possible order.
+    fn total_order(&self) -> Result<Vec<usize>> {
+        let pages = self
+            .successors
+            .iter()
+            .flat_map(|(page, next)| next.iter().chain([page]))
+            .copied()
+            .unique()
+            .sorted()
+            .collect_vec();
+        let order = self.sort(&pages)?;
+        if let Some((a, b)) = order
+            .iter()
+            .tuple_windows()
+            .find(|(a, b)| !self.precedes(**a, **b))
+        {
+            bail!("No rule orders pages {} and {}", a, b);
+        }
+        Ok(order)
+    }
+
+    // Kahn's algorithm over the pages of the update only, rules between other
+    // pages do not matter. Ties keep the original order of the update, and a
+    // page listed twice has no place in the order so it is rejected.
+    fn sort(&self, update: &[usize]) -> Result<Vec<usize>> {
+        if let Some(page) = update.iter().duplicates().next() {
+            bail!("Update lists page {} more than once", page);
+        }
+        let pages = update.iter().copied().collect::<HashSet<_>>();
+        let mut in_degree = update
+            .iter()
+            .map(|page| (*page, 0))
+            .collect::<HashMap<_, _>>();
+        for (a, b) in update.iter().tuple_combinations() {
+            if self.precedes(*a, *b) {
+                *in_degree.get_mut(b).unwrap() += 1;
+            }
+            if self.precedes(*b, *a) {
+                *in_degree.get_mut(a).unwrap() += 1;
+            }
+        }
+
+        let mut sorted = Vec::with_capacity(update.len());
+        while let Some(page) = update
+            .iter()
+            .find(|page| in_degree.get(*page) == Some(&0))
+            .copied()
+        {
+            in_degree.remove(&page);
+            sorted.push(page);
+            for next in self.successors.get(&page).into_iter().flatten() {
+                if pages.contains(next) {
+                    in_degree.entry(*next).and_modify(|d| *d -= 1);
+                }
+            }
+        }
+
+        if sorted.len() < pages.len() {
+            let remaining = in_degree.keys().copied().collect::<HashSet<_>>();
+            bail!(
+                "Rules form a cycle between pages {}",
+                self.find_cycle(&remaining).iter().join(" -> ")
+            );
+        }
+        Ok(sorted)
+    }
+
+    // Every page left over by the topological sort has a predecessor that is
+    // left over too, so walking predecessors must eventually repeat a page.
+    fn find_cycle(&self, pages: &HashSet<usize>) -> Vec<usize> {
+        let mut path = vec![*pages.iter().min().unwrap()];
+        loop {
+            let page = *path.last().unwrap();
+            let previous = *pages
+                .iter()
+                .filter(|p| self.precedes(**p, page))
+                .min()
+                .unwrap();
+            if let Some(start) = path.iter().position(|p| *p == previous) {
+                let mut cycle = path[start..].to_vec();
+                cycle.reverse();
+                let smallest = cycle.iter().position_min().unwrap();
+                cycle.rotate_left(smallest);
+                cycle.push(cycle[0]);
+                return cycle;
+            }
+            path.push(previous);
+        }
+    }
+}
+
+fn read_input<R: BufRead>(reader: R) -> Result<(PrecedenceGraph, Vec<Vec<usize>>)> {
+    let mut graph = PrecedenceGraph::default();
+    let mut updates = vec![];
+
+    let mut lines = reader.lines().map_while(Result::ok);
+    for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+        let (a, b) = line
+            .split_once("|")
+            .ok_or_else(|| anyhow!("Invalid rule: {}", line))?;
+        graph.add_rule(a.parse()?, b.parse()?);
+    }
+    for line in lines.filter(|line| !line.is_empty()) {
+        let update = line
+            .split(',')
+            .map(|x| x.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?;
+        updates.push(update);
+    }
+
+    Ok((graph, updates))
+}
+
+fn middle_page(update: &[usize]) -> usize {
+    update[update.len() / 2]
+}
+
 fn main() -> Result<()> {
     start_day(DAY);
 
@@ -48,49 +265,11 @@ fn main() -> Result<()> {
     println!("=== Part 1 ===");
 
     fn part1<R: BufRead>(reader: R) -> Result<usize> {
-        let mut rules: HashMap<usize, HashSet<usize>> = HashMap::new();
-        let mut rules_read = false;
-
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .filter_map(|line| {
-                println!("{}", line);
-                if !rules_read {
-                    if line.is_empty() {
-                        rules_read = true;
-                    } else if let Some((a, b)) = line.split_once("|") {
-                        if let (Ok(a), Ok(b)) = (a.parse::<usize>(), b.parse::<usize>()) {
-                            rules.entry(a).or_default().insert(b);
-                        }
-                    }
-                    None
-                } else {
-                    let update = line
-                        .split(',')
-                        .filter_map(|x| x.parse::<usize>().ok())
-                        .collect_vec();
-
-                    let mut seen = HashSet::new();
-                    for (i, c) in update.iter().enumerate() {
-                        seen.insert(*c);
-                        if i == 0 {
-                            continue;
-                        }
-
-                        if let Some(should_follow) = rules.get(c) {
-                            if should_follow.intersection(&seen).count() != 0 {
-                                println!(
-                                    "Update {:?} is invalid, expected  all of {:?} to follow {}",
-                                    update, should_follow, c
-                                );
-                                return None;
-                            }
-                        }
-                    }
-                    Some(update[update.len() / 2])
-                }
-            })
+        let (graph, updates) = read_input(reader)?;
+        let answer = updates
+            .iter()
+            .filter(|update| graph.is_ordered(update))
+            .map(|update| middle_page(update))
             .sum::<usize>();
         Ok(answer)
     }
@@ -106,64 +285,11 @@ fn main() -> Result<()> {
     println!("\n=== Part 2 ===");
 
     fn part2<R: BufRead>(reader: R) -> Result<usize> {
-        let mut rules: HashMap<usize, HashSet<usize>> = HashMap::new();
-        let mut rules_read = false;
-
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .filter_map(|line| {
-                println!("{}", line);
-                if !rules_read {
-                    if line.is_empty() {
-                        rules_read = true;
-                    } else if let Some((a, b)) = line.split_once("|") {
-                        if let (Ok(a), Ok(b)) = (a.parse::<usize>(), b.parse::<usize>()) {
-                            rules.entry(a).or_default().insert(b);
-                        }
-                    }
-                    None
-                } else {
-                    let mut update = line
-                        .split(',')
-                        .filter_map(|x| x.parse::<usize>().ok())
-                        .collect_vec();
-                    let orig_update = update.clone();
-
-                    let mut has_changes = false;
-                    let mut seen = HashSet::new();
-                    for i in 0..update.len() {
-                        let c = update[i];
-                        seen.insert(c);
-                        if i == 0 {
-                            continue;
-                        }
-
-                        if let Some(should_follow) = rules.get(&c) {
-                            if should_follow.intersection(&seen).count() != 0 {
-                                let mut k = i;
-                                for j in (0..i).rev() {
-                                    let d = update[j];
-                                    if should_follow.contains(&d) {
-                                        update.swap(k, j);
-                                        k -= 1;
-                                        println!("Swap {} to {}: {:?}", c, d, update);
-                                        has_changes = true;
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    if has_changes {
-                        println!("Update {:?} becomes {:?}", orig_update, update);
-                        Some(update[update.len() / 2])
-                    } else {
-                        None
-                    }
-                }
-            })
-            .sum::<usize>();
+        let (graph, updates) = read_input(reader)?;
+        let mut answer = 0;
+        for update in updates.iter().filter(|update| !graph.is_ordered(update)) {
+            answer += middle_page(&graph.sort(update)?);
+        }
         Ok(answer)
     }
 
@@ -174,5 +300,150 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Validation
+    println!("\n=== Validation ===");
+
+    fn validate_updates<R: BufRead>(reader: R, verbose: bool) -> Result<()> {
+        let (graph, updates) = read_input(reader)?;
+        let (mut invalid, mut violations, mut ambiguous, mut undetermined) = (0, 0, 0, 0);
+        for update in updates.iter() {
+            let report = graph.validate(update)?;
+            if !report.violations.is_empty() {
+                invalid += 1;
+                violations += report.violations.len();
+                if verbose {
+                    println!("Update {:?} becomes {:?}", update, report.corrected);
+                    report.violations.iter().for_each(|v| println!("  {}", v));
+                }
+            }
+            ambiguous += !report.unique as usize;
+            undetermined += !report.middle_determined as usize;
+        }
+        println!(
+            "Invalid updates: {} with {} violations, ambiguous orders: {}, undetermined middle pages: {}",
+            invalid, violations, ambiguous, undetermined
+        );
+        match graph.total_order() {
+            Ok(order) => println!("Rules are a total order over {} pages", order.len()),
+            Err(error) => println!("Rules are not a total order: {}", error),
+        }
+        Ok(())
+    }
+
+    validate_updates(BufReader::new(TEST.as_bytes()), true)?;
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(validate_updates(input_file, false)?);
+    // endregion
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{read_input, PrecedenceGraph, Violation, TEST};
+
+    #[test]
+    fn test_sort_updates() {
+        let (graph, updates) = read_input(TEST.as_bytes()).unwrap();
+        assert_eq!(updates.len(), 6);
+
+        let ordered = updates
+            .iter()
+            .map(|u| graph.is_ordered(u))
+            .collect::<Vec<_>>();
+        assert_eq!(ordered, vec![true, true, true, false, false, false]);
+
+        assert_eq!(graph.sort(&updates[3]).unwrap(), vec![97, 75, 47, 61, 53]);
+        assert_eq!(graph.sort(&updates[4]).unwrap(), vec![61, 29, 13]);
+        assert_eq!(graph.sort(&updates[5]).unwrap(), vec![97, 75, 47, 29, 13]);
+        assert_eq!(graph.sort(&updates[0]).unwrap(), updates[0]);
+
+        let error = graph.sort(&[97, 13, 75, 13]).unwrap_err();
+        assert_eq!(error.to_string(), "Update lists page 13 more than once");
+    }
+
+    #[test]
+    fn test_sort_cycle() {
+        let mut graph = PrecedenceGraph::default();
+        graph.add_rule(1, 2);
+        graph.add_rule(2, 3);
+        graph.add_rule(3, 1);
+        graph.add_rule(3, 4);
+
+        // The cycle only matters when all of its pages are in the update
+        assert_eq!(graph.sort(&[4, 3, 2]).unwrap(), vec![2, 3, 4]);
+        let error = graph.sort(&[4, 3, 2, 1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Rules form a cycle between pages 1 -> 2 -> 3 -> 1"
+        );
+
+        // Two pages ordered both ways
+        graph.add_rule(5, 6);
+        graph.add_rule(6, 5);
+        let error = graph.sort(&[5, 6]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Rules form a cycle between pages 5 -> 6 -> 5"
+        );
+        assert!(graph.sort(&[6, 4, 5]).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let (graph, updates) = read_input(TEST.as_bytes()).unwrap();
+
+        let report = graph.validate(&updates[0]).unwrap();
+        assert!(report.violations.is_empty());
+        assert!(report.unique && report.middle_determined);
+
+        let report = graph.validate(&updates[4]).unwrap();
+        assert_eq!(
+            report.violations,
+            vec![Violation {
+                before: (29, 2),
+                after: (13, 1)
+            }]
+        );
+        assert_eq!(
+            report.violations[0].to_string(),
+            "29|13 violated: 13 at index 1 precedes 29 at index 2"
+        );
+        assert_eq!(report.corrected, vec![61, 29, 13]);
+
+        let report = graph.validate(&updates[5]).unwrap();
+        assert_eq!(report.violations.len(), 4);
+        assert!(report.unique);
+
+        assert_eq!(
+            graph.total_order().unwrap(),
+            vec![97, 75, 47, 61, 53, 29, 13]
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_orders() {
+        let mut graph = PrecedenceGraph::default();
+        graph.add_rule(1, 2);
+        graph.add_rule(2, 3);
+        graph.add_rule(3, 4);
+        graph.add_rule(3, 5);
+
+        // 4 and 5 can swap places, but neither of them is in the middle
+        let report = graph.validate(&[5, 4, 3, 2, 1]).unwrap();
+        assert_eq!(report.corrected, vec![1, 2, 3, 5, 4]);
+        assert!(!report.unique);
+        assert!(report.middle_determined);
+
+        let report = graph.validate(&[3, 4, 5]).unwrap();
+        assert!(!report.unique);
+        assert!(!report.middle_determined);
+
+        assert_eq!(
+            graph.total_order().unwrap_err().to_string(),
+            "No rule orders pages 4 and 5"
+        );
+        graph.add_rule(5, 1);
+        assert!(graph.total_order().is_err());
+    }
+}
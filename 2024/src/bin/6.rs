@@ -1,3 +1,5 @@
+use adv_code_2024::export::{Exporter, Palette};
+use adv_code_2024::viewer::{Simulation, Viewer};
 use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
@@ -105,6 +107,66 @@ fn walk(map: &TileMap, mut pos: Position, mut dir: Direction) -> (usize, bool) {
     }
 }
 
+struct GuardSimulation {
+    map: TileMap,
+    path: Vec<(Position, Direction)>,
+}
+
+impl GuardSimulation {
+    pub fn new(map: TileMap, mut pos: Position, mut dir: Direction) -> Self {
+        let mut path = vec![(pos, dir)];
+        let mut visited_directions = HashSet::from([(pos, dir)]);
+
+        while let Some(new_pos) = leap(pos, dir, 1).filter(|p| map.in_bounds(*p)) {
+            if map.tiles.contains_key(&new_pos) {
+                dir = dir.turn_right();
+            } else {
+                pos = new_pos;
+            }
+            if !visited_directions.insert((pos, dir)) {
+                break;
+            }
+            path.push((pos, dir));
+        }
+
+        Self { map, path }
+    }
+}
+
+impl Simulation for GuardSimulation {
+    type State = (Position, Direction, HashSet<Position>);
+
+    fn state(&self, step: usize) -> Self::State {
+        let (pos, dir) = self.path[step];
+        let visited = self.path[..=step].iter().map(|(p, _)| *p).collect();
+        (pos, dir, visited)
+    }
+
+    fn render(&self, (pos, dir, visited): &Self::State) -> Vec<String> {
+        (0..=self.map.area.1 .0)
+            .map(|i| {
+                (0..=self.map.area.1 .1)
+                    .map(|j| match self.map.tiles.get(&(i, j)) {
+                        _ if (i, j) == *pos => match dir {
+                            Direction::N => '^',
+                            Direction::S => 'v',
+                            Direction::W => '<',
+                            _ => '>',
+                        },
+                        Some(tile) => *tile,
+                        None if visited.contains(&(i, j)) => 'X',
+                        None => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.path.len())
+    }
+}
+
 fn main() -> Result<()> {
     start_day(DAY);
 
@@ -166,12 +228,53 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    if std::env::args().any(|arg| arg == "--inspect") {
+        let mut map = TileMap::read(BufReader::new(File::open(INPUT_FILE)?), '.')?;
+        let (pos, dir) = map.find_player().expect("player not found");
+        map.tiles.remove(&pos);
+
+        let simulation = GuardSimulation::new(map, pos, dir);
+        let mut viewer = Viewer::new(&simulation).with_search(|(pos, dir, _)| {
+            simulation
+                .map
+                .tiles
+                .contains_key(&leap(*pos, *dir, 1).unwrap_or(*pos))
+        });
+        viewer.run()?;
+    }
+
+    if std::env::args().any(|arg| arg == "--export") {
+        let mut map = TileMap::read(BufReader::new(File::open(INPUT_FILE)?), '.')?;
+        let (pos, dir) = map.find_player().expect("player not found");
+        map.tiles.remove(&pos);
+
+        let simulation = GuardSimulation::new(map, pos, dir);
+        let palette = "^v<>".chars().fold(
+            Palette::default()
+                .with('.', [16, 24, 32])
+                .with('#', [160, 160, 160])
+                .with('X', [200, 160, 64]),
+            |palette, guard| palette.with(guard, [230, 60, 60]),
+        );
+        let exporter = Exporter::new(palette).with_scale(2);
+        std::fs::create_dir_all("export")?;
+        exporter.write_simulation(
+            &simulation,
+            (0..).step_by(50),
+            1000,
+            50,
+            &mut File::create("export/6-guard.png")?,
+        )?;
+        println!("Exported guard path to export/6-guard.png");
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{walk, TileMap};
+    use crate::{walk, GuardSimulation, TileMap};
+    use adv_code_2024::viewer::Simulation;
     use std::io::BufReader;
 
     const LOOP1: &str = r#"....#.....
@@ -256,4 +359,20 @@ mod tests {
             assert!(is_looping, "Loop is not detected for {}", i + 1);
         }
     }
+
+    #[test]
+    fn test_guard_simulation() {
+        let mut map =
+            TileMap::read(BufReader::new(crate::TEST.as_bytes()), '.').expect("parse error");
+        let (pos, dir) = map.find_player().expect("player not found");
+        map.tiles.remove(&pos);
+
+        let simulation = GuardSimulation::new(map, pos, dir);
+        let (_, _, visited) = simulation.state(simulation.steps().unwrap() - 1);
+        assert_eq!(visited.len(), 41);
+
+        let frame = simulation.render(&simulation.state(5));
+        assert_eq!(frame[1], "....^....#");
+        assert_eq!(frame[6], ".#..X.....");
+    }
 }
@@ -2,7 +2,7 @@ use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
-use itertools::Itertools;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::result::Result::Ok;
@@ -20,63 +20,395 @@ const TEST: &str = r#"190: 10 19
 21037: 9 7 18 13
 292: 11 6 16 20"#;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+type Value = u128;
+
+// Values are unsigned, so `apply` returns `None` whenever the result would
+// be negative, fractional or overflow.
+trait Operator {
+    fn symbol(&self) -> String;
+
+    fn apply(&self, a: Value, b: Value) -> Option<Value>;
+
+    // Left operands `a` such that `a op arg == target`, or `None` when the
+    // operator can not be inverted and the solver has to search forward.
+    fn unapply(&self, _target: Value, _arg: Value) -> Option<Vec<Value>> {
+        None
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn right_associative(&self) -> bool {
+        false
+    }
+}
+
+impl<O: Operator + ?Sized> Operator for &O {
+    fn symbol(&self) -> String {
+        (**self).symbol()
+    }
+
+    fn apply(&self, a: Value, b: Value) -> Option<Value> {
+        (**self).apply(a, b)
+    }
+
+    fn unapply(&self, target: Value, arg: Value) -> Option<Vec<Value>> {
+        (**self).unapply(target, arg)
+    }
+
+    fn precedence(&self) -> u8 {
+        (**self).precedence()
+    }
+
+    fn right_associative(&self) -> bool {
+        (**self).right_associative()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Operation {
     Mul,
     Add,
-    Concat,
+    Sub,
+    Div,
+    Pow,
+    Xor,
+    Concat(u32),
 }
 
-fn concat(a: usize, b: usize) -> usize {
-    a * 10usize.pow(b.ilog10() + 1) + b
+impl Operation {
+    // Concatenation needs at least two digits to write numbers with
+    fn shift(base: u32, arg: Value) -> Option<Value> {
+        let base = Some(base as Value).filter(|base| *base >= 2)?;
+        base.checked_pow(arg.checked_ilog(base).unwrap_or(0) + 1)
+    }
+
+    // Exact integer root, found by bisection so no precision is lost
+    fn root(target: Value, exp: u32) -> Option<Value> {
+        let (mut lo, mut hi) = (0, target);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            match mid.checked_pow(exp) {
+                Some(power) if power <= target => lo = mid,
+                _ => hi = mid - 1,
+            }
+        }
+        (lo.checked_pow(exp) == Some(target)).then_some(lo)
+    }
 }
 
-fn calculate(arguments: &[usize], operations: &[Operation]) -> usize {
-    let mut result = arguments[0];
+impl Operator for Operation {
+    fn symbol(&self) -> String {
+        match self {
+            Operation::Mul => "*".into(),
+            Operation::Add => "+".into(),
+            Operation::Sub => "-".into(),
+            Operation::Div => "/".into(),
+            Operation::Pow => "^".into(),
+            Operation::Xor => "xor".into(),
+            Operation::Concat(10) => "||".into(),
+            Operation::Concat(base) => format!("||{}", base),
+        }
+    }
 
-    for i in 0..operations.len() {
-        let arg = arguments[i + 1];
-        let op = &operations[i];
+    fn apply(&self, a: Value, b: Value) -> Option<Value> {
+        match self {
+            Operation::Mul => a.checked_mul(b),
+            Operation::Add => a.checked_add(b),
+            Operation::Sub => a.checked_sub(b),
+            Operation::Div => (b != 0 && a.is_multiple_of(b)).then(|| a / b),
+            Operation::Pow => a.checked_pow(b.try_into().ok()?),
+            Operation::Xor => Some(a ^ b),
+            Operation::Concat(base) => a.checked_mul(Self::shift(*base, b)?)?.checked_add(b),
+        }
+    }
 
-        match op {
-            Operation::Mul => {
-                result *= arg;
+    fn unapply(&self, target: Value, arg: Value) -> Option<Vec<Value>> {
+        let candidates = match self {
+            // Any left operand works when multiplying by zero
+            Operation::Mul if arg == 0 => return None,
+            Operation::Mul => target.is_multiple_of(arg).then(|| target / arg),
+            Operation::Add => target.checked_sub(arg),
+            Operation::Sub => target.checked_add(arg),
+            Operation::Div => target.checked_mul(arg).filter(|_| arg != 0),
+            Operation::Pow if arg == 0 => return None,
+            Operation::Pow => Self::root(target, arg.try_into().ok()?),
+            Operation::Xor => Some(target ^ arg),
+            Operation::Concat(base) => {
+                let shift = Self::shift(*base, arg)?;
+                (target % shift == arg).then(|| target / shift)
             }
-            Operation::Add => {
-                result += arg;
+        };
+        Some(candidates.into_iter().collect())
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Operation::Xor => 0,
+            Operation::Add | Operation::Sub => 1,
+            Operation::Mul | Operation::Div => 2,
+            Operation::Pow => 3,
+            Operation::Concat(_) => 4,
+        }
+    }
+
+    fn right_associative(&self) -> bool {
+        *self == Operation::Pow
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    LeftToRight,
+    Precedence,
+}
+
+// Applies pending operators that bind at least as tight as `next`, or all of
+// them at the end of the expression. Returns `false` if any of them is
+// undefined for its operands.
+fn reduce<O: Operator>(
+    values: &mut Vec<Value>,
+    pending: &mut Vec<O>,
+    next: Option<&O>,
+    order: Order,
+) -> bool {
+    while let Some(top) = pending.last() {
+        let binds = match (next, order) {
+            (None, _) | (_, Order::LeftToRight) => true,
+            (Some(next), Order::Precedence) => {
+                top.precedence() > next.precedence()
+                    || (top.precedence() == next.precedence() && !next.right_associative())
             }
-            Operation::Concat => {
-                result = concat(result, arg);
+        };
+        if !binds {
+            break;
+        }
+        let (b, a) = (values.pop().unwrap(), values.pop().unwrap());
+        match top.apply(a, b) {
+            Some(value) => values.push(value),
+            None => return false,
+        }
+        pending.pop();
+    }
+    true
+}
+
+fn calculate<O: Operator + Clone>(
+    arguments: &[Value],
+    operations: &[O],
+    order: Order,
+) -> Option<Value> {
+    let mut values = vec![arguments[0]];
+    let mut pending = vec![];
+    for (op, arg) in operations.iter().zip(&arguments[1..]) {
+        if !reduce(&mut values, &mut pending, Some(op), order) {
+            return None;
+        }
+        pending.push(op.clone());
+        values.push(*arg);
+    }
+    reduce(&mut values, &mut pending, None, order).then(|| values[0])
+}
+
+// Tries every operator sequence depth-first, sharing the partially reduced
+// expression between sequences with a common prefix.
+fn search_forward<O: Operator + Clone>(
+    args: &[Value],
+    operators: &[O],
+    order: Order,
+    accept: &dyn Fn(Value) -> bool,
+) -> Option<Vec<O>> {
+    fn search<O: Operator + Clone>(
+        args: &[Value],
+        operators: &[O],
+        order: Order,
+        accept: &dyn Fn(Value) -> bool,
+        mut values: Vec<Value>,
+        mut pending: Vec<O>,
+        chosen: &mut Vec<O>,
+    ) -> bool {
+        let Some((&arg, rest)) = args.split_first() else {
+            return reduce(&mut values, &mut pending, None, order) && accept(values[0]);
+        };
+        for op in operators {
+            let (mut values, mut pending) = (values.clone(), pending.clone());
+            if !reduce(&mut values, &mut pending, Some(op), order) {
+                continue;
+            }
+            pending.push(op.clone());
+            values.push(arg);
+            chosen.push(op.clone());
+            if search(rest, operators, order, accept, values, pending, chosen) {
+                return true;
+            }
+            chosen.pop();
+        }
+        false
+    }
+
+    let (&first, rest) = args.split_first()?;
+    let mut chosen = vec![];
+    search(
+        rest,
+        operators,
+        order,
+        accept,
+        vec![first],
+        vec![],
+        &mut chosen,
+    )
+    .then_some(chosen)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Equation {
+    target: Value,
+    args: Vec<Value>,
+}
+
+impl std::str::FromStr for Equation {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let (target, args) = line
+            .split_once(": ")
+            .ok_or_else(|| anyhow!("Invalid equation: {}", line))?;
+        let args = args
+            .split_whitespace()
+            .map(|a| a.parse::<Value>())
+            .collect::<Result<Vec<_>, _>>()?;
+        ensure!(!args.is_empty(), "Equation without arguments: {}", line);
+        Ok(Equation {
+            target: target.parse()?,
+            args,
+        })
+    }
+}
+
+impl Equation {
+    fn solve<O: Operator + Clone>(&self, operators: &[O], order: Order) -> Option<Vec<O>> {
+        match order {
+            Order::LeftToRight => solve_backward(self.target, &self.args, operators),
+            Order::Precedence => {
+                search_forward(&self.args, operators, order, &|value| value == self.target)
             }
         }
     }
 
-    result
+    fn count_solutions<O: Operator>(&self, operators: &[O]) -> u128 {
+        count_backward(self.target, &self.args, operators, &mut HashMap::new())
+    }
+
+    fn expression<O: Operator>(&self, operations: &[O]) -> String {
+        let mut expression = self.args[0].to_string();
+        for (op, arg) in operations.iter().zip(&self.args[1..]) {
+            expression += &format!(" {} {}", op.symbol(), arg);
+        }
+        expression
+    }
 }
 
-fn product_recursive<T: Clone>(
-    alphabet: &[T],
-    n: usize,
-    current: &mut Vec<T>,
-    result: &mut Vec<Vec<T>>,
-) {
-    if current.len() == n {
-        result.push(current.to_vec());
-        return;
+// Works right-to-left from the target, so that every operation has to undo
+// cleanly and most branches die after a step or two. Operators that can not be
+// undone fall back to a forward search over the remaining prefix.
+fn solve_backward<O: Operator + Clone>(
+    target: Value,
+    args: &[Value],
+    operators: &[O],
+) -> Option<Vec<O>> {
+    let (&last, rest) = args.split_last()?;
+    if rest.is_empty() {
+        return (last == target).then(Vec::new);
     }
+    operators.iter().find_map(|op| {
+        let mut ops = match op.unapply(target, last) {
+            Some(candidates) => candidates
+                .into_iter()
+                .find_map(|target| solve_backward(target, rest, operators))?,
+            None => search_forward(rest, operators, Order::LeftToRight, &|value| {
+                op.apply(value, last) == Some(target)
+            })?,
+        };
+        ops.push(op.clone());
+        Some(ops)
+    })
+}
 
-    for c in alphabet.iter() {
-        current.push(c.clone());
-        product_recursive(alphabet, n, current, result);
-        current.pop();
+// Counts left-to-right operator sequences that reach the target. Prefixes are
+// memoized by their length and the value they have to reach, so sequences
+// sharing a suffix are counted once.
+fn count_backward<O: Operator>(
+    target: Value,
+    args: &[Value],
+    operators: &[O],
+    memo: &mut HashMap<(usize, Value), u128>,
+) -> u128 {
+    let Some((&last, rest)) = args.split_last() else {
+        return 0;
+    };
+    if rest.is_empty() {
+        return (last == target) as u128;
+    }
+    if let Some(count) = memo.get(&(args.len(), target)) {
+        return *count;
+    }
+
+    let count = operators
+        .iter()
+        .map(|op| match op.unapply(target, last) {
+            Some(candidates) => candidates
+                .into_iter()
+                .map(|target| count_backward(target, rest, operators, memo))
+                .sum(),
+            None => prefix_values(rest, operators)
+                .into_iter()
+                .filter(|(value, _)| op.apply(*value, last) == Some(target))
+                .map(|(_, count)| count)
+                .sum::<u128>(),
+        })
+        .sum();
+    memo.insert((args.len(), target), count);
+    count
+}
+
+// Every value a prefix can reach, with the number of operator sequences
+// reaching it
+fn prefix_values<O: Operator>(args: &[Value], operators: &[O]) -> HashMap<Value, u128> {
+    let mut values = HashMap::from([(args[0], 1)]);
+    for arg in &args[1..] {
+        let mut next = HashMap::new();
+        for (value, count) in values {
+            for op in operators {
+                if let Some(value) = op.apply(value, *arg) {
+                    *next.entry(value).or_insert(0) += count;
+                }
+            }
+        }
+        values = next;
     }
+    values
 }
 
-fn product<T: Clone>(alphabet: &[T], n: usize) -> Vec<Vec<T>> {
-    let mut result = Vec::new();
-    let mut current = Vec::new();
-    product_recursive(alphabet, n, &mut current, &mut result);
-    result
+fn read_equations<R: BufRead>(reader: R) -> Result<Vec<Equation>> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse())
+        .collect()
+}
+
+fn calibration_result<O: Operator + Clone>(
+    equations: &[Equation],
+    operations: &[O],
+    order: Order,
+) -> Value {
+    equations
+        .iter()
+        .filter(|equation| equation.solve(operations, order).is_some())
+        .map(|equation| equation.target)
+        .sum()
 }
 
 fn main() -> Result<()> {
@@ -85,28 +417,13 @@ fn main() -> Result<()> {
     //region Part 1
     println!("=== Part 1 ===");
 
-    fn part1<R: BufRead>(reader: R) -> Result<usize> {
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .filter_map(|line| {
-                let (result, args) = line.split_once(": ")?;
-                let result = result.parse::<usize>().ok()?;
-                let args = args
-                    .split_whitespace()
-                    .flat_map(|a| a.parse::<usize>().ok())
-                    .collect_vec();
-
-                let op_alphabet = vec![Operation::Add, Operation::Mul];
-                for ops in product(&op_alphabet, args.len() - 1) {
-                    let ops_result = calculate(&args, &ops);
-                    if ops_result == result {
-                        return Some(result);
-                    }
-                }
-                None
-            })
-            .sum::<usize>();
+    fn part1<R: BufRead>(reader: R) -> Result<Value> {
+        let equations = read_equations(reader)?;
+        let answer = calibration_result(
+            &equations,
+            &[Operation::Add, Operation::Mul],
+            Order::LeftToRight,
+        );
         Ok(answer)
     }
 
@@ -120,28 +437,19 @@ fn main() -> Result<()> {
     // region Part 2
     println!("\n=== Part 2 ===");
 
-    fn part2<R: BufRead>(reader: R) -> Result<usize> {
-        let answer = reader
-            .lines()
-            .map_while(Result::ok)
-            .filter_map(|line| {
-                let (result, args) = line.split_once(": ")?;
-                let result = result.parse::<usize>().ok()?;
-                let args = args
-                    .split_whitespace()
-                    .flat_map(|a| a.parse::<usize>().ok())
-                    .collect_vec();
-
-                let op_alphabet = vec![Operation::Add, Operation::Mul, Operation::Concat];
-                for ops in product(&op_alphabet, args.len() - 1) {
-                    let ops_result = calculate(&args, &ops);
-                    if ops_result == result {
-                        return Some(result);
-                    }
-                }
-                None
-            })
-            .sum::<usize>();
+    fn part2<R: BufRead>(reader: R) -> Result<Value> {
+        let equations = read_equations(reader)?;
+        let operations = [Operation::Add, Operation::Mul, Operation::Concat(10)];
+        for equation in equations.iter().take(3) {
+            if let Some(ops) = equation.solve(&operations, Order::LeftToRight) {
+                assert_eq!(
+                    calculate(&equation.args, &ops, Order::LeftToRight),
+                    Some(equation.target)
+                );
+                println!("{} = {}", equation.target, equation.expression(&ops));
+            }
+        }
+        let answer = calibration_result(&equations, &operations, Order::LeftToRight);
         Ok(answer)
     }
 
@@ -152,28 +460,262 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Solution counts
+    println!("\n=== Solution counts ===");
+
+    fn count_solutions<R: BufRead>(reader: R) -> Result<Value> {
+        let equations = read_equations(reader)?;
+        let operations = [Operation::Add, Operation::Mul, Operation::Concat(10)];
+        let counts = equations
+            .iter()
+            .map(|equation| (equation, equation.count_solutions(&operations)))
+            .collect::<Vec<_>>();
+
+        let solvable = counts.iter().filter(|(_, count)| *count > 0).count();
+        let ambiguous = counts.iter().filter(|(_, count)| *count > 1).count();
+        println!(
+            "Solvable: {}, ambiguous: {} of {} equations",
+            solvable,
+            ambiguous,
+            counts.len()
+        );
+        if let Some((equation, count)) = counts.iter().max_by_key(|(_, count)| *count) {
+            println!("Most solutions: {} for target {}", count, equation.target);
+        }
+
+        let answer = counts
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(equation, _)| equation.target)
+            .sum();
+        Ok(answer)
+    }
+
+    assert_eq!(11387, count_solutions(BufReader::new(TEST.as_bytes()))?);
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    let result = time_snippet!(count_solutions(input_file)?);
+    println!("Result = {}", result);
+    // endregion
+
+    // region Operator variants
+    println!("\n=== Operator variants ===");
+
+    fn compare_variants<R: BufRead>(reader: R, orders: &[Order]) -> Result<()> {
+        use Operation::*;
+
+        let equations = read_equations(reader)?;
+        let variants: [&[Operation]; 5] = [
+            &[Add, Mul, Concat(10)],
+            &[Add, Mul, Sub, Div],
+            &[Add, Mul, Pow],
+            &[Add, Mul, Xor],
+            &[Add, Mul, Concat(2), Concat(16)],
+        ];
+        for variant in variants {
+            for order in orders {
+                println!(
+                    "{:?} {:?}: {}",
+                    variant.iter().map(|op| op.symbol()).collect::<Vec<_>>(),
+                    order,
+                    calibration_result(&equations, variant, *order)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    compare_variants(TEST.as_bytes(), &[Order::LeftToRight, Order::Precedence])?;
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(compare_variants(input_file, &[Order::LeftToRight])?);
+    // endregion
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{calculate, Operation};
+    use crate::{calculate, Equation, Operation, Operator, Order, Value, TEST};
+    use itertools::Itertools;
 
     #[test]
     fn test_calculate() {
-        assert_eq!(calculate(&[12, 345], &[Operation::Concat]), 12345);
+        let ltr = Order::LeftToRight;
+        assert_eq!(
+            calculate(&[12, 345], &[Operation::Concat(10)], ltr),
+            Some(12345)
+        );
 
         assert_eq!(
-            calculate(&[17, 8, 14], &[Operation::Concat, Operation::Add]),
-            192,
+            calculate(&[17, 8, 14], &[Operation::Concat(10), Operation::Add], ltr),
+            Some(192),
         );
 
         assert_eq!(
             calculate(
                 &[6, 8, 6, 15],
-                &[Operation::Mul, Operation::Concat, Operation::Mul]
+                &[Operation::Mul, Operation::Concat(10), Operation::Mul],
+                ltr
             ),
-            7290,
+            Some(7290),
+        );
+    }
+
+    #[test]
+    fn test_calculate_with_precedence() {
+        use Operation::*;
+        let calc = |args: &[Value], ops: &[Operation]| {
+            (
+                calculate(args, ops, Order::LeftToRight),
+                calculate(args, ops, Order::Precedence),
+            )
+        };
+
+        assert_eq!(
+            calc(&[11, 6, 16, 20], &[Add, Mul, Add]),
+            (Some(292), Some(127))
+        );
+        assert_eq!(calc(&[2, 3, 2], &[Pow, Pow]), (Some(64), Some(512)));
+        assert_eq!(calc(&[1, 2, 3], &[Add, Concat(10)]), (Some(33), Some(24)));
+        assert_eq!(calc(&[5, 3, 3, 2], &[Xor, Add, Mul]), (Some(18), Some(12)));
+        assert_eq!(calc(&[10, 5, 3], &[Sub, Mul]), (Some(15), None));
+        assert_eq!(calc(&[12, 4, 3], &[Div, Div]), (Some(1), Some(1)));
+        assert_eq!(calc(&[12, 5], &[Div]), (None, None));
+        assert_eq!(calc(&[2, 5], &[Concat(2)]), (Some(21), Some(21)));
+        assert_eq!(calc(&[Value::MAX, 1], &[Add]), (None, None));
+    }
+
+    #[test]
+    fn test_unapply() {
+        use Operation::*;
+        let unapply =
+            |op: Operation, target, arg| op.unapply(target, arg).map(|c| c.first().copied());
+
+        assert_eq!(unapply(Add, 10, 3), Some(Some(7)));
+        assert_eq!(unapply(Add, 3, 10), Some(None));
+        assert_eq!(unapply(Mul, 12, 4), Some(Some(3)));
+        assert_eq!(unapply(Mul, 12, 5), Some(None));
+        assert_eq!(unapply(Mul, 0, 0), None);
+        assert_eq!(unapply(Sub, 3, 10), Some(Some(13)));
+        assert_eq!(unapply(Div, 3, 10), Some(Some(30)));
+        assert_eq!(unapply(Div, 3, 0), Some(None));
+        assert_eq!(unapply(Pow, 1024, 10), Some(Some(2)));
+        assert_eq!(unapply(Pow, 1025, 10), Some(None));
+        assert_eq!(unapply(Pow, 1, 0), None);
+        assert_eq!(unapply(Xor, 6, 3), Some(Some(5)));
+        assert_eq!(unapply(Concat(10), 12345, 345), Some(Some(12)));
+        assert_eq!(unapply(Concat(10), 12345, 45), Some(Some(123)));
+        assert_eq!(unapply(Concat(10), 12345, 346), Some(None));
+        assert_eq!(unapply(Concat(10), 120, 0), Some(Some(12)));
+        assert_eq!(unapply(Concat(2), 13, 5), Some(Some(1)));
+
+        // Roots beyond the precision of floating point numbers
+        let big = (1 << 60) + 1;
+        assert_eq!(unapply(Pow, big * big, 2), Some(Some(big)));
+        assert_eq!(unapply(Pow, big * big - 1, 2), Some(None));
+        assert_eq!(unapply(Pow, 3u128.pow(80), 80), Some(Some(3)));
+        assert_eq!(unapply(Pow, Value::MAX, 1), Some(Some(Value::MAX)));
+
+        // No digits to concatenate with
+        for base in [0, 1] {
+            assert_eq!(Concat(base).apply(1, 2), None);
+            assert_eq!(unapply(Concat(base), 12, 2), None);
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        let operations = [Operation::Add, Operation::Mul, Operation::Concat(10)];
+        let ltr = Order::LeftToRight;
+
+        let equation = "7290: 6 8 6 15".parse::<Equation>().unwrap();
+        let ops = equation.solve(&operations, ltr).unwrap();
+        assert_eq!(
+            ops,
+            vec![Operation::Mul, Operation::Concat(10), Operation::Mul]
         );
+        assert_eq!(equation.expression(&ops), "6 * 8 || 6 * 15");
+        assert_eq!(equation.solve(&operations[..2], ltr), None);
+
+        let equation = "21037: 9 7 18 13".parse::<Equation>().unwrap();
+        assert_eq!(equation.solve(&operations, ltr), None);
+
+        let equation = "5: 5".parse::<Equation>().unwrap();
+        assert_eq!(equation.solve(&operations, ltr), Some(vec![]));
+
+        assert!("5".parse::<Equation>().is_err());
+        assert!("5: ".parse::<Equation>().is_err());
+    }
+
+    #[test]
+    fn test_solve_with_precedence() {
+        let operations = [Operation::Add, Operation::Mul];
+
+        let equation = "292: 11 6 16 20".parse::<Equation>().unwrap();
+        assert!(equation.solve(&operations, Order::LeftToRight).is_some());
+        assert_eq!(equation.solve(&operations, Order::Precedence), None);
+
+        let equation = "127: 11 6 16 20".parse::<Equation>().unwrap();
+        let ops = equation.solve(&operations, Order::Precedence).unwrap();
+        assert_eq!(equation.expression(&ops), "11 + 6 * 16 + 20");
+    }
+
+    // Only implements `apply`, so the solver has to search forward
+    struct Diff;
+
+    impl Operator for Diff {
+        fn symbol(&self) -> String {
+            "~".into()
+        }
+
+        fn apply(&self, a: Value, b: Value) -> Option<Value> {
+            Some(a.abs_diff(b))
+        }
+    }
+
+    #[test]
+    fn test_custom_operator() {
+        let operations: [&dyn Operator; 2] = [&Operation::Add, &Diff];
+        let equation = "4: 3 10 3".parse::<Equation>().unwrap();
+
+        let ops = equation.solve(&operations, Order::LeftToRight).unwrap();
+        assert_eq!(equation.expression(&ops), "3 ~ 10 ~ 3");
+        let ops = equation.solve(&operations, Order::Precedence).unwrap();
+        assert_eq!(calculate(&equation.args, &ops, Order::Precedence), Some(4));
+
+        // Multiplying by zero can not be undone either
+        let equation = "0: 7 3 0".parse::<Equation>().unwrap();
+        let ops = equation.solve(&[Operation::Add, Operation::Mul], Order::LeftToRight);
+        assert_eq!(ops, Some(vec![Operation::Add, Operation::Mul]));
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        let operations = [Operation::Add, Operation::Mul, Operation::Concat(10)];
+        for line in TEST.lines() {
+            let equation = line.parse::<Equation>().unwrap();
+            let brute_force = (1..equation.args.len())
+                .map(|_| operations)
+                .multi_cartesian_product()
+                .filter(|ops| {
+                    calculate(&equation.args, ops, Order::LeftToRight) == Some(equation.target)
+                })
+                .count();
+            assert_eq!(equation.count_solutions(&operations), brute_force as u128);
+        }
+
+        let equation = "3267: 81 40 27".parse::<Equation>().unwrap();
+        assert_eq!(equation.count_solutions(&operations[..2]), 2);
+
+        // Both prefixes work when multiplying by zero
+        let equation = "0: 7 3 0".parse::<Equation>().unwrap();
+        assert_eq!(equation.count_solutions(&operations[..2]), 2);
+
+        let equation =
+            "340282366920938463463374607431768211455: 18446744073709551615 18446744073709551617"
+                .parse::<Equation>()
+                .unwrap();
+        assert_eq!(equation.target, Value::MAX);
+        assert_eq!(equation.count_solutions(&operations), 1);
     }
 }
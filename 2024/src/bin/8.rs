@@ -2,9 +2,11 @@ use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
-use std::collections::{HashMap, HashSet};
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::RangeInclusive;
 use std::result::Result::Ok;
 
 const DAY: &str = "8";
@@ -23,6 +25,14 @@ const TEST: &str = r#"............
 ............
 ............"#;
 
+const TEST_COORDINATES: &str = r#"area=1000000000,1000000000
+a 0,0
+a 1,1
+a 7,7
+b 0,2
+b 2,0
+c 999999999,0"#;
+
 type AbsolutePosition = (isize, isize);
 type AbsoluteRectangle = (AbsolutePosition, AbsolutePosition);
 
@@ -65,45 +75,291 @@ impl TileMap {
         })
     }
 
-    pub fn in_bounds(&self, pos: AbsolutePosition) -> bool {
-        rectangle_includes(self.area, pos)
+    // Maps too large to draw are given as an `area=ROWS,COLUMNS` header and
+    // one `FREQUENCY ROW,COLUMN` line per antenna.
+    pub fn read_coordinates<R: BufRead>(input: R) -> Result<TileMap> {
+        let mut lines = input.lines().map_while(Result::ok);
+        let header = lines.next().unwrap_or_default();
+        let (rows, columns) = header
+            .strip_prefix("area=")
+            .and_then(|area| area.split_once(","))
+            .ok_or_else(|| anyhow!("Invalid area header: {}", header))?;
+        let (rows, columns) = (
+            rows.trim().parse::<isize>()?,
+            columns.trim().parse::<isize>()?,
+        );
+        ensure!(
+            rows > 0 && columns > 0,
+            "Area must not be empty: {}",
+            header
+        );
+        let area = ((0, 0), (rows - 1, columns - 1));
+
+        let mut tiles = HashMap::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let (frequency, pos) = line
+                .trim()
+                .split_once(" ")
+                .and_then(|(frequency, pos)| {
+                    Some((frequency.parse::<char>().ok()?, pos.split_once(",")?))
+                })
+                .ok_or_else(|| anyhow!("Invalid antenna: {}", line))?;
+            let pos = (
+                pos.0.trim().parse::<isize>()?,
+                pos.1.trim().parse::<isize>()?,
+            );
+            ensure!(
+                rectangle_includes(area, pos),
+                "Antenna {} is outside of the area",
+                line
+            );
+            ensure!(
+                tiles.insert(pos, frequency).is_none(),
+                "Two antennas at {:?}",
+                pos
+            );
+        }
+
+        Ok(TileMap { tiles, area })
     }
 }
 
-fn calc_antinode_locations(s1: AbsolutePosition, s2: AbsolutePosition) -> Vec<AbsolutePosition> {
-    let dx = s2.0 - s1.0;
-    let dy = s2.1 - s1.1;
+// Values of `t` for which `from + t * step` is inside the area
+fn steps_in_area(
+    from: AbsolutePosition,
+    step: AbsolutePosition,
+    area: &AbsoluteRectangle,
+) -> RangeInclusive<isize> {
+    let axis = |from: isize, step: isize, min: isize, max: isize| match step {
+        0 if (min..=max).contains(&from) => (isize::MIN, isize::MAX),
+        0 => (1, 0),
+        _ if step > 0 => (
+            -(from - min).div_euclid(step),
+            (max - from).div_euclid(step),
+        ),
+        _ => (
+            -(max - from).div_euclid(-step),
+            (from - min).div_euclid(-step),
+        ),
+    };
+    let (lo_x, hi_x) = axis(from.0, step.0, area.0 .0, area.1 .0);
+    let (lo_y, hi_y) = axis(from.1, step.1, area.0 .1, area.1 .1);
+    lo_x.max(lo_y)..=hi_x.min(hi_y)
+}
 
-    let locations = vec![(s1.0 - dx, s1.1 - dy), (s2.0 + dx, s2.1 + dy)];
-    locations
+// Which points in line with a pair of antennas resonate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Harmonics {
+    // Points `k` antenna distances beyond each antenna
+    Only(isize),
+    // Points up to `n` antenna distances beyond each antenna, including the
+    // antennas themselves
+    UpTo(isize),
+    // Every grid point in line with the antennas
+    Line,
+    // Grid points splitting the segment between the antennas into `n` equal
+    // parts, 3 giving the 1/3 and 2/3 points
+    Fractions(isize),
 }
 
-fn calculate_line_points_in_area(
-    s1: AbsolutePosition,
-    s2: AbsolutePosition,
-    area: &AbsoluteRectangle,
-) -> Vec<AbsolutePosition> {
-    let (x1, y1) = s1;
-    let (x2, y2) = s2;
-
-    let a = y2 - y1;
-    let b = x1 - x2;
-    let c = x2 * y1 - x1 * y2;
-
-    let (min_x, min_y) = (area.0 .0, area.0 .1);
-    let (max_x, max_y) = (area.1 .0, area.1 .1);
-
-    let mut points = Vec::new();
-    for x in min_x..=max_x {
-        for y in min_y..=max_y {
-            // Check if the point (x, y) satisfies the line equation
-            if a * x + b * y + c == 0 {
-                points.push((x, y));
+impl Harmonics {
+    fn antinodes(
+        &self,
+        s1: AbsolutePosition,
+        s2: AbsolutePosition,
+        area: &AbsoluteRectangle,
+    ) -> Vec<AbsolutePosition> {
+        let d = (s2.0 - s1.0, s2.1 - s1.1);
+        let at = |t: isize| (s1.0 + t * d.0, s1.1 + t * d.1);
+
+        match *self {
+            Harmonics::Only(k) => [at(-k), at(1 + k)]
+                .into_iter()
+                .filter(|p| rectangle_includes(*area, *p))
+                .collect(),
+            Harmonics::UpTo(n) => {
+                let steps = steps_in_area(s1, d, area);
+                let first = (-n).max(*steps.start());
+                let last = (1 + n).min(*steps.end());
+                (first..=last).map(at).collect()
+            }
+            Harmonics::Line => {
+                let g = gcd(d.0 as i128, d.1 as i128) as isize;
+                let step = (d.0 / g, d.1 / g);
+                steps_in_area(s1, step, area)
+                    .map(|t| (s1.0 + t * step.0, s1.1 + t * step.1))
+                    .collect()
             }
+            Harmonics::Fractions(n) => (1..n)
+                .filter(|i| (d.0 * i) % n == 0 && (d.1 * i) % n == 0)
+                .map(|i| (s1.0 + d.0 * i / n, s1.1 + d.1 * i / n))
+                .collect(),
         }
     }
+}
 
-    points
+struct Resonance {
+    harmonics: Harmonics,
+    frequencies: Box<dyn Fn(char) -> bool>,
+}
+
+impl Resonance {
+    fn new(harmonics: Harmonics) -> Self {
+        Self {
+            harmonics,
+            frequencies: Box::new(|_| true),
+        }
+    }
+
+    fn with_frequencies(mut self, frequencies: impl Fn(char) -> bool + 'static) -> Self {
+        self.frequencies = Box::new(frequencies);
+        self
+    }
+
+    fn stations(&self, map: &TileMap) -> BTreeMap<char, Vec<AbsolutePosition>> {
+        let mut stations_by_name: BTreeMap<char, Vec<_>> = BTreeMap::new();
+        map.tiles
+            .iter()
+            .filter(|(_, c)| (self.frequencies)(**c))
+            .for_each(|(p, c)| {
+                stations_by_name.entry(*c).or_default().push(*p);
+            });
+        stations_by_name
+    }
+
+    // Antinodes of every resonating frequency, found pair by pair
+    fn antinodes(&self, map: &TileMap) -> BTreeMap<char, HashSet<AbsolutePosition>> {
+        self.stations(map)
+            .into_iter()
+            .map(|(frequency, ps)| {
+                let antinodes = ps
+                    .iter()
+                    .tuple_combinations()
+                    .flat_map(|(s1, s2)| self.harmonics.antinodes(*s1, *s2, &map.area))
+                    .collect();
+                (frequency, antinodes)
+            })
+            .collect()
+    }
+
+    // Number of distinct antinodes, whole lines are counted without visiting
+    // their points so that any area size works
+    fn count(&self, map: &TileMap) -> usize {
+        match self.harmonics {
+            Harmonics::Line => {
+                let lines = self
+                    .stations(map)
+                    .values()
+                    .flat_map(|ps| ps.iter().tuple_combinations())
+                    .map(|(s1, s2)| GridLine::through(*s1, *s2))
+                    .unique_by(GridLine::key)
+                    .collect_vec();
+                count_line_points(&lines, &map.area)
+            }
+            _ => count_antinodes(&self.antinodes(map)),
+        }
+    }
+}
+
+// Grid points `origin + t * step`, the step coordinates are coprime so every
+// grid point on the line is one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GridLine {
+    origin: AbsolutePosition,
+    step: AbsolutePosition,
+}
+
+impl GridLine {
+    fn through(s1: AbsolutePosition, s2: AbsolutePosition) -> Self {
+        let d = (s2.0 - s1.0, s2.1 - s1.1);
+        let g = gcd(d.0 as i128, d.1 as i128) as isize;
+        let step = match (d.0 / g, d.1 / g) {
+            step if step > (0, 0) => step,
+            (x, y) => (-x, -y),
+        };
+        Self { origin: s1, step }
+    }
+
+    // The step and the cross product of the step with any point of the line
+    // identify it
+    fn key(&self) -> (AbsolutePosition, i128) {
+        (self.step, self.offset())
+    }
+
+    fn offset(&self) -> i128 {
+        self.step.0 as i128 * self.origin.1 as i128 - self.step.1 as i128 * self.origin.0 as i128
+    }
+
+    fn points_in_area(&self, area: &AbsoluteRectangle) -> usize {
+        let steps = steps_in_area(self.origin, self.step, area);
+        (*steps.end() as i128 - *steps.start() as i128 + 1).max(0) as usize
+    }
+
+    // Grid point where two distinct lines cross
+    fn intersection(&self, other: &GridLine) -> Option<AbsolutePosition> {
+        let (a1, b1) = (self.step.0 as i128, self.step.1 as i128);
+        let (a2, b2) = (other.step.0 as i128, other.step.1 as i128);
+        let (o1, o2) = (self.offset(), other.offset());
+
+        let det = a1 * b2 - a2 * b1;
+        if det == 0 {
+            return None;
+        }
+        let (x, y) = (o1 * a2 - a1 * o2, b2 * o1 - b1 * o2);
+        if x % det != 0 || y % det != 0 {
+            return None;
+        }
+        Some(((x / det).try_into().ok()?, (y / det).try_into().ok()?))
+    }
+}
+
+// Distinct grid points on any of the distinct lines, every point where `m`
+// lines cross is counted `m` times line by line
+fn count_line_points(lines: &[GridLine], area: &AbsoluteRectangle) -> usize {
+    let mut crossings: HashMap<AbsolutePosition, HashSet<usize>> = HashMap::new();
+    for ((i, l1), (j, l2)) in lines.iter().enumerate().tuple_combinations() {
+        if let Some(pos) = l1
+            .intersection(l2)
+            .filter(|p| rectangle_includes(*area, *p))
+        {
+            crossings.entry(pos).or_default().extend([i, j]);
+        }
+    }
+
+    let points = lines.iter().map(|l| l.points_in_area(area)).sum::<usize>();
+    let repeated = crossings
+        .values()
+        .map(|lines| lines.len() - 1)
+        .sum::<usize>();
+    points - repeated
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrequencyReport {
+    frequency: char,
+    antinodes: usize,
+    // Antinodes that some other frequency has as well
+    shared: usize,
+}
+
+fn report(antinodes: &BTreeMap<char, HashSet<AbsolutePosition>>) -> Vec<FrequencyReport> {
+    let mut frequencies: HashMap<AbsolutePosition, usize> = HashMap::new();
+    for pos in antinodes.values().flatten() {
+        *frequencies.entry(*pos).or_default() += 1;
+    }
+
+    antinodes
+        .iter()
+        .map(|(frequency, positions)| FrequencyReport {
+            frequency: *frequency,
+            antinodes: positions.len(),
+            shared: positions.iter().filter(|p| frequencies[*p] > 1).count(),
+        })
+        .collect()
+}
+
+fn count_antinodes(antinodes: &BTreeMap<char, HashSet<AbsolutePosition>>) -> usize {
+    antinodes.values().flatten().unique().count()
 }
 
 fn main() -> Result<()> {
@@ -114,31 +370,7 @@ fn main() -> Result<()> {
 
     fn part1<R: BufRead>(reader: R) -> Result<usize> {
         let map = TileMap::read(reader, '.')?;
-
-        let mut stations_by_name: HashMap<char, Vec<_>> = HashMap::new();
-        map.tiles.iter().for_each(|(p, c)| {
-            stations_by_name.entry(*c).or_default().push(*p);
-        });
-
-        let mut antinode_locations = HashSet::new();
-        stations_by_name.iter().for_each(|(_, ps)| {
-            for i in 0..ps.len() {
-                for j in 0..ps.len() {
-                    if i == j {
-                        continue;
-                    }
-
-                    calc_antinode_locations(ps[i], ps[j])
-                        .into_iter()
-                        .filter(|a| map.in_bounds(*a))
-                        .for_each(|a| {
-                            antinode_locations.insert(a);
-                        });
-                }
-            }
-        });
-
-        let answer = antinode_locations.len();
+        let answer = Resonance::new(Harmonics::Only(1)).count(&map);
         Ok(answer)
     }
 
@@ -154,27 +386,7 @@ fn main() -> Result<()> {
 
     fn part2<R: BufRead>(reader: R) -> Result<usize> {
         let map = TileMap::read(reader, '.')?;
-
-        let mut stations_by_name: HashMap<char, Vec<_>> = HashMap::new();
-        map.tiles.iter().for_each(|(p, c)| {
-            stations_by_name.entry(*c).or_default().push(*p);
-        });
-
-        let mut antinode_locations = HashSet::new();
-        stations_by_name.iter().for_each(|(_, ps)| {
-            for i in 0..ps.len() {
-                for j in 0..ps.len() {
-                    if i == j {
-                        continue;
-                    }
-
-                    antinode_locations
-                        .extend(calculate_line_points_in_area(ps[i], ps[j], &map.area));
-                }
-            }
-        });
-
-        let answer = antinode_locations.len();
+        let answer = Resonance::new(Harmonics::Line).count(&map);
         Ok(answer)
     }
 
@@ -185,6 +397,86 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Resonance
+    println!("\n=== Resonance ===");
+
+    fn resonance<R: BufRead>(reader: R, resonance: Resonance) -> Result<Vec<FrequencyReport>> {
+        let map = TileMap::read(reader, '.')?;
+        Ok(report(&resonance.antinodes(&map)))
+    }
+
+    assert_eq!(
+        vec![
+            FrequencyReport {
+                frequency: '0',
+                antinodes: 10,
+                shared: 1
+            },
+            FrequencyReport {
+                frequency: 'A',
+                antinodes: 5,
+                shared: 1
+            }
+        ],
+        resonance(
+            BufReader::new(TEST.as_bytes()),
+            Resonance::new(Harmonics::Only(1))
+        )?
+    );
+
+    for (name, harmonics) in [
+        ("second harmonic", Harmonics::Only(2)),
+        ("harmonics up to 3", Harmonics::UpTo(3)),
+        ("thirds", Harmonics::Fractions(3)),
+    ] {
+        let input_file = BufReader::new(File::open(INPUT_FILE)?);
+        let reports = resonance(
+            input_file,
+            Resonance::new(harmonics).with_frequencies(|c| c.is_ascii_digit()),
+        )?;
+        println!(
+            "Digit frequencies, {}: {} antinodes, {} shared",
+            name,
+            reports.iter().map(|r| r.antinodes).sum::<usize>(),
+            reports.iter().map(|r| r.shared).sum::<usize>()
+        );
+    }
+    // endregion
+
+    // region Large maps
+    println!("\n=== Large maps ===");
+
+    fn large_map<R: BufRead>(reader: R) -> Result<(usize, usize)> {
+        let map = TileMap::read_coordinates(reader)?;
+        Ok((
+            Resonance::new(Harmonics::Only(1)).count(&map),
+            Resonance::new(Harmonics::Line).count(&map),
+        ))
+    }
+
+    assert_eq!(
+        (3, 1_000_000_002),
+        large_map(BufReader::new(TEST_COORDINATES.as_bytes()))?
+    );
+
+    // The puzzle map spread over a million times larger area
+    let map = TileMap::read(BufReader::new(File::open(INPUT_FILE)?), '.')?;
+    let scale = 1_000_000;
+    let coordinates = std::iter::once(format!(
+        "area={},{}",
+        (map.area.1 .0 + 1) * scale,
+        (map.area.1 .1 + 1) * scale
+    ))
+    .chain(
+        map.tiles
+            .iter()
+            .map(|((i, j), c)| format!("{} {},{}", c, i * scale, j * scale)),
+    )
+    .join("\n");
+    let (part1, part2) = time_snippet!(large_map(BufReader::new(coordinates.as_bytes()))?);
+    println!("Result = {}, {}", part1, part2);
+    // endregion
+
     Ok(())
 }
 
@@ -193,62 +485,187 @@ mod tests {
     use super::*;
     #[test]
     fn test_antinode_locations() {
+        let area = ((-20, -20), (20, 20));
         assert_eq!(
-            calc_antinode_locations((3, 4), (5, 5)),
+            Harmonics::Only(1).antinodes((3, 4), (5, 5), &area),
             vec![(1, 3), (7, 6)]
         );
         assert_eq!(
-            calc_antinode_locations((5, 5), (3, 4)),
+            Harmonics::Only(1).antinodes((5, 5), (3, 4), &area),
             vec![(7, 6), (1, 3)]
         );
 
         assert_eq!(
-            calc_antinode_locations((3, 4), (4, 8)),
+            Harmonics::Only(1).antinodes((3, 4), (4, 8), &area),
             vec![(2, 0), (5, 12)]
         );
         assert_eq!(
-            calc_antinode_locations((4, 8), (3, 4)),
+            Harmonics::Only(1).antinodes((4, 8), (3, 4), &area),
             vec![(5, 12), (2, 0)]
         );
 
         assert_eq!(
-            calc_antinode_locations((4, 8), (5, 5)),
+            Harmonics::Only(1).antinodes((4, 8), (5, 5), &area),
             vec![(3, 11), (6, 2)]
         );
         assert_eq!(
-            calc_antinode_locations((5, 5), (4, 8)),
+            Harmonics::Only(1).antinodes((5, 5), (4, 8), &area),
             vec![(6, 2), (3, 11)]
         );
     }
 
+    fn line_points(
+        s1: AbsolutePosition,
+        s2: AbsolutePosition,
+        area: &AbsoluteRectangle,
+    ) -> Vec<AbsolutePosition> {
+        Harmonics::Line
+            .antinodes(s1, s2, area)
+            .into_iter()
+            .sorted()
+            .collect()
+    }
+
     #[test]
     fn test_calculate_line_points_in_area() {
         let area = ((0, 0), (9, 9));
         assert_eq!(
-            calculate_line_points_in_area((0, 0), (2, 1), &area),
+            line_points((0, 0), (2, 1), &area),
             vec![(0, 0), (2, 1), (4, 2), (6, 3), (8, 4)]
         );
         assert_eq!(
-            calculate_line_points_in_area((2, 1), (0, 0), &area),
+            line_points((2, 1), (0, 0), &area),
             vec![(0, 0), (2, 1), (4, 2), (6, 3), (8, 4)]
         );
 
         assert_eq!(
-            calculate_line_points_in_area((0, 0), (1, 3), &area),
+            line_points((0, 0), (1, 3), &area),
             vec![(0, 0), (1, 3), (2, 6), (3, 9)]
         );
         assert_eq!(
-            calculate_line_points_in_area((1, 3), (0, 0), &area),
+            line_points((1, 3), (0, 0), &area),
             vec![(0, 0), (1, 3), (2, 6), (3, 9)]
         );
 
         assert_eq!(
-            calculate_line_points_in_area((1, 3), (2, 1), &area),
+            line_points((1, 3), (2, 1), &area),
             vec![(0, 5), (1, 3), (2, 1)]
         );
         assert_eq!(
-            calculate_line_points_in_area((2, 1), (1, 3), &area),
+            line_points((2, 1), (1, 3), &area),
             vec![(0, 5), (1, 3), (2, 1)]
         );
     }
+
+    #[test]
+    fn test_harmonics() {
+        let area = ((0, 0), (9, 9));
+        let (s1, s2) = ((3, 0), (4, 2));
+        assert_eq!(Harmonics::Only(1).antinodes(s1, s2, &area), vec![(5, 4)]);
+        assert_eq!(Harmonics::Only(2).antinodes(s1, s2, &area), vec![(6, 6)]);
+        assert_eq!(
+            Harmonics::UpTo(3).antinodes(s1, s2, &area),
+            vec![(3, 0), (4, 2), (5, 4), (6, 6), (7, 8)]
+        );
+        assert_eq!(
+            Harmonics::UpTo(1).antinodes(s1, s2, &area),
+            vec![(3, 0), (4, 2), (5, 4)]
+        );
+
+        let (s1, s2) = ((0, 0), (3, 6));
+        assert_eq!(
+            Harmonics::Fractions(3).antinodes(s1, s2, &area),
+            vec![(1, 2), (2, 4)]
+        );
+        assert_eq!(Harmonics::Fractions(2).antinodes(s1, s2, &area), vec![]);
+        assert_eq!(
+            Harmonics::Line.antinodes(s2, s1, &area),
+            vec![(4, 8), (3, 6), (2, 4), (1, 2), (0, 0)]
+        );
+        assert_eq!(steps_in_area((5, 5), (0, 3), &area), -1..=1);
+        assert_eq!(steps_in_area((5, 5), (-2, 0), &area), -2..=2);
+    }
+
+    #[test]
+    fn test_resonance() {
+        let map = TileMap::read(BufReader::new(crate::TEST.as_bytes()), '.').unwrap();
+
+        let antinodes = Resonance::new(Harmonics::Line)
+            .with_frequencies(|c| c == 'A')
+            .antinodes(&map);
+        assert_eq!(antinodes.keys().collect_vec(), vec![&'A']);
+        assert_eq!(count_antinodes(&antinodes), 16);
+
+        let antinodes = Resonance::new(Harmonics::Line).antinodes(&map);
+        let reports = report(&antinodes);
+        assert_eq!(count_antinodes(&antinodes), 34);
+        // With two frequencies every shared antinode is counted for both
+        assert_eq!(reports[0].shared, reports[1].shared);
+    }
+
+    #[test]
+    fn test_read_coordinates() {
+        let map = TileMap::read_coordinates(crate::TEST_COORDINATES.as_bytes()).unwrap();
+        assert_eq!(map.area, ((0, 0), (999_999_999, 999_999_999)));
+        assert_eq!(map.tiles.len(), 6);
+        assert_eq!(map.tiles[&(999_999_999, 0)], 'c');
+
+        assert!(TileMap::read_coordinates("a 0,0".as_bytes()).is_err());
+        assert!(TileMap::read_coordinates("area=0,5".as_bytes()).is_err());
+        assert!(TileMap::read_coordinates("area=5,5\na 5,0".as_bytes()).is_err());
+        assert!(TileMap::read_coordinates("area=5,5\na 1,1\nb 1,1".as_bytes()).is_err());
+        assert!(TileMap::read_coordinates("area=5,5\nab 1,1".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_grid_lines() {
+        let l1 = GridLine::through((0, 0), (2, 2));
+        let l2 = GridLine::through((7, 7), (3, 3));
+        assert_eq!(l1.step, (1, 1));
+        assert_eq!(l1.key(), l2.key());
+
+        let l3 = GridLine::through((0, 2), (2, 0));
+        assert_eq!(l3.step, (1, -1));
+        assert_eq!(l1.intersection(&l3), Some((1, 1)));
+        assert_eq!(l1.intersection(&GridLine::through((0, 1), (1, 2))), None);
+        // The lines cross between grid points
+        assert_eq!(l1.intersection(&GridLine::through((0, 1), (1, 0))), None);
+
+        let area = ((0, 0), (9, 9));
+        assert_eq!(l1.points_in_area(&area), 10);
+        assert_eq!(l3.points_in_area(&area), 3);
+        assert_eq!(count_line_points(&[l1, l3], &area), 12);
+    }
+
+    #[test]
+    fn test_count_matches_antinodes() {
+        let coordinates = "area=12,12\n".to_string()
+            + &crate::TEST
+                .lines()
+                .enumerate()
+                .flat_map(|(i, line)| {
+                    line.chars()
+                        .enumerate()
+                        .filter(|(_, c)| *c != '.')
+                        .map(move |(j, c)| format!("{} {},{}", c, i, j))
+                })
+                .join("\n");
+        let map = TileMap::read_coordinates(coordinates.as_bytes()).unwrap();
+        assert_eq!(Resonance::new(Harmonics::Line).count(&map), 34);
+
+        // Lines of both frequencies cross and overlap each other
+        let map = TileMap::read_coordinates(
+            "area=10,10\na 0,0\na 3,3\na 0,9\nb 5,5\nb 9,9\nb 9,0\nc 0,4\nc 8,4".as_bytes(),
+        )
+        .unwrap();
+        for harmonics in [Harmonics::Line, Harmonics::Only(1), Harmonics::UpTo(2)] {
+            let resonance = Resonance::new(harmonics);
+            assert_eq!(
+                resonance.count(&map),
+                count_antinodes(&resonance.antinodes(&map)),
+                "{:?}",
+                harmonics
+            );
+        }
+    }
 }
@@ -1,9 +1,12 @@
+use adv_code_2024::viewer::{Simulation, Viewer};
 use adv_code_2024::*;
 use anyhow::*;
 use code_timing_macros::time_snippet;
 use const_format::concatcp;
 use itertools::Itertools;
-use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::result::Result::Ok;
@@ -15,13 +18,27 @@ const TEST: &str = r#"2333133121414131402"#;
 
 #[derive(Debug, Clone, Copy)]
 enum DiskEntry {
-    Block { file_id: u16, len: u8 },
-    Free { len: u8 },
+    Block { file_id: u16, len: usize },
+    Free { len: usize },
 }
 
-#[derive(Debug, Default)]
+impl DiskEntry {
+    fn len(&self) -> usize {
+        match *self {
+            DiskEntry::Block { len, .. } | DiskEntry::Free { len } => len,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 struct DiskMap {
     entries: Vec<DiskEntry>,
+    moves: MoveStats,
+    // Index and block offset of the first entry that may be non-empty free
+    // space; files before it have nowhere to move, so it only moves forward
+    free_cursor: (usize, usize),
+    // Where the last file attempt ended; files are searched left of it first
+    file_hint: usize,
 }
 
 impl std::fmt::Display for DiskMap {
@@ -30,8 +47,8 @@ impl std::fmt::Display for DiskMap {
             .entries
             .iter()
             .map(|entry| match entry {
-                DiskEntry::Block { file_id, len } => file_id.to_string().repeat(*len as usize),
-                DiskEntry::Free { len } => ".".repeat(*len as usize),
+                DiskEntry::Block { file_id, len } => file_id.to_string().repeat(*len),
+                DiskEntry::Free { len } => ".".repeat(*len),
             })
             .join("");
         write!(f, "{}", repr)
@@ -46,18 +63,21 @@ impl DiskMap {
             .map(|(i, c)| {
                 let d = c.to_digit(10).unwrap_or_default();
                 if i % 2 != 0 {
-                    DiskEntry::Free { len: d as u8 }
+                    DiskEntry::Free { len: d as usize }
                 } else {
                     let entry = DiskEntry::Block {
                         file_id,
-                        len: d as u8,
+                        len: d as usize,
                     };
                     file_id += 1;
                     entry
                 }
             })
             .collect_vec();
-        DiskMap { entries }
+        DiskMap {
+            entries,
+            ..Default::default()
+        }
     }
 
     pub fn checksum(&self) -> usize {
@@ -67,14 +87,14 @@ impl DiskMap {
             .cloned()
             .map(|entry| match entry {
                 DiskEntry::Block { file_id, len } => {
-                    let block_checksum: usize = (0usize..len as usize)
+                    let block_checksum: usize = (0usize..len)
                         .map(|i| (block_id + i) * file_id as usize)
                         .sum();
-                    block_id += len as usize;
+                    block_id += len;
                     block_checksum
                 }
                 DiskEntry::Free { len } => {
-                    block_id += len as usize;
+                    block_id += len;
                     0usize
                 }
             })
@@ -83,6 +103,8 @@ impl DiskMap {
 
     pub fn compacted(mut self) -> Self {
         let mut compacted_entries = vec![];
+        let mut moved_files = HashSet::new();
+        let mut blocks_moved = 0usize;
         let mut entry_idx = 0;
         let mut last_entry_idx = self.entries.len() - 1;
 
@@ -116,7 +138,11 @@ impl DiskMap {
                                 file_id: last_file_id,
                                 len: last_len,
                             } => {
+                                if *last_len > 0 {
+                                    moved_files.insert(*last_file_id);
+                                }
                                 if free_len >= last_len {
+                                    blocks_moved += *last_len;
                                     compacted_entries.push(DiskEntry::Block {
                                         file_id: *last_file_id,
                                         len: *last_len,
@@ -128,6 +154,7 @@ impl DiskMap {
                                         break;
                                     }
                                 } else {
+                                    blocks_moved += *free_len;
                                     compacted_entries.push(DiskEntry::Block {
                                         file_id: *last_file_id,
                                         len: *free_len,
@@ -152,44 +179,374 @@ impl DiskMap {
 
         DiskMap {
             entries: compacted_entries,
+            moves: MoveStats {
+                files_moved: self.moves.files_moved + moved_files.len(),
+                blocks_moved: self.moves.blocks_moved + blocks_moved,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn defrag_compacted(self) -> Self {
+        self.allocated(&mut MoveOnce::new(FirstFit))
+    }
+
+    pub fn allocated<P: AllocationPolicy>(mut self, policy: &mut P) -> Self {
+        let max_file_id = self
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                DiskEntry::Block { file_id, .. } => Some(*file_id),
+                DiskEntry::Free { .. } => None,
+            })
+            .max();
+        let Some(max_file_id) = max_file_id else {
+            return self;
+        };
+
+        loop {
+            let mut has_moves = false;
+            for file_id in (0..=max_file_id).rev() {
+                if self.move_file(file_id, policy) {
+                    has_moves = true;
+                }
+            }
+            if !has_moves || policy.single_pass() {
+                break;
+            }
         }
+
+        self
     }
 
-    pub fn defrag_compacted(mut self) -> Self {
-        for i in (0..self.entries.len()).rev() {
-            let DiskEntry::Block { len, .. } = self.entries[i] else {
-                continue;
-            };
+    fn move_file<P: AllocationPolicy>(&mut self, file_id: u16, policy: &mut P) -> bool {
+        let (start, offset) = self.advance_free_cursor();
+        let Some(i) = self.find_file(file_id) else {
+            return false;
+        };
+        self.file_hint = i;
+        let DiskEntry::Block { len, .. } = self.entries[i] else {
+            unreachable!("file {} is a block", file_id);
+        };
+        let file = FileSpan { file_id, len };
+        if file.len == 0 || i < start {
+            return false;
+        }
 
-            for j in 0..i {
-                let DiskEntry::Free { len: free_len } = self.entries[j] else {
-                    continue;
+        // Spans are handed out lazily, so policies that pick an early span
+        // do not pay for scanning up to the file
+        let mut offered = vec![];
+        let mut candidates = self.entries[start..i]
+            .iter()
+            .enumerate()
+            .scan(offset, |offset, (k, entry)| {
+                let span = match *entry {
+                    DiskEntry::Free { len } => Some((
+                        start + k,
+                        FreeSpan {
+                            offset: *offset,
+                            len,
+                        },
+                    )),
+                    DiskEntry::Block { .. } => None,
                 };
+                *offset += entry.len();
+                Some(span)
+            })
+            .flatten()
+            .filter(|(_, span)| span.len >= file.len)
+            .inspect(|candidate| offered.push(*candidate))
+            .map(|(_, span)| span);
+        let selected = policy.select(&file, &mut candidates);
+        let Some((j, span)) =
+            selected.and_then(|selected| offered.into_iter().find(|(_, span)| *span == selected))
+        else {
+            return false;
+        };
 
-                match len.cmp(&free_len) {
-                    Ordering::Less => {
-                        self.entries[j] = DiskEntry::Free { len };
-                        self.entries.swap(i, j);
-                        self.entries.insert(
-                            j + 1,
-                            DiskEntry::Free {
-                                len: free_len - len,
-                            },
-                        );
-                        break;
-                    }
-                    Ordering::Equal => {
-                        self.entries.swap(i, j);
-                        break;
-                    }
-                    Ordering::Greater => {}
+        self.entries[i] = DiskEntry::Free { len: file.len };
+        self.entries[j] = DiskEntry::Block {
+            file_id,
+            len: file.len,
+        };
+        let mut vacated = i;
+        if span.len > file.len {
+            self.entries.insert(
+                j + 1,
+                DiskEntry::Free {
+                    len: span.len - file.len,
+                },
+            );
+            vacated += 1;
+        }
+        // The span the file went to was already as large as it could be, so
+        // only the space it left behind can join its neighbours
+        self.file_hint = self.coalesce_free(vacated);
+
+        self.moves.files_moved += 1;
+        self.moves.blocks_moved += file.len;
+        true
+    }
+
+    // Looks left of the hint first, which is where the puzzle's decreasing
+    // file id order always finds the next file
+    fn find_file(&self, file_id: u16) -> Option<usize> {
+        let is_file = |entry: &DiskEntry| matches!(entry, DiskEntry::Block { file_id: id, .. } if *id == file_id);
+        let hint = self.file_hint.min(self.entries.len());
+        self.entries[..hint].iter().rposition(is_file).or_else(|| {
+            self.entries[hint..]
+                .iter()
+                .position(is_file)
+                .map(|k| hint + k)
+        })
+    }
+
+    fn advance_free_cursor(&mut self) -> (usize, usize) {
+        let (idx, offset) = &mut self.free_cursor;
+        while let Some(entry) = self.entries.get(*idx) {
+            match *entry {
+                DiskEntry::Free { len } if len > 0 => break,
+                entry => *offset += entry.len(),
+            }
+            *idx += 1;
+        }
+        self.free_cursor
+    }
+
+    // Merges the free span at `idx` with free spans right next to it and
+    // returns where the merged span ends up
+    fn coalesce_free(&mut self, idx: usize) -> usize {
+        if let Some(&DiskEntry::Free { len: next_len }) = self.entries.get(idx + 1) {
+            if let DiskEntry::Free { len } = &mut self.entries[idx] {
+                *len += next_len;
+                self.entries.remove(idx + 1);
+            }
+        }
+        if let Some(&DiskEntry::Free { len: next_len }) = self.entries.get(idx) {
+            if let Some(DiskEntry::Free { len }) = idx.checked_sub(1).map(|i| &mut self.entries[i])
+            {
+                *len += next_len;
+                self.entries.remove(idx);
+                return idx - 1;
+            }
+        }
+        idx
+    }
+
+    pub fn fragmentation(&self) -> FragmentationStats {
+        let last_block_idx = self
+            .entries
+            .iter()
+            .rposition(|entry| matches!(entry, DiskEntry::Block { len, .. } if *len > 0))
+            .unwrap_or_default();
+
+        let mut stats = FragmentationStats {
+            files_moved: self.moves.files_moved,
+            blocks_moved: self.moves.blocks_moved,
+            ..Default::default()
+        };
+        let mut current_span = 0usize;
+        for entry in self.entries[..last_block_idx].iter() {
+            match entry {
+                DiskEntry::Free { len } => current_span += *len,
+                DiskEntry::Block { len, .. } if *len > 0 && current_span > 0 => {
+                    stats.free_spans += 1;
+                    stats.largest_free_span = stats.largest_free_span.max(current_span);
+                    current_span = 0;
                 }
+                DiskEntry::Block { .. } => {}
             }
         }
+        if current_span > 0 {
+            stats.free_spans += 1;
+            stats.largest_free_span = stats.largest_free_span.max(current_span);
+        }
+        stats
+    }
+}
 
-        DiskMap {
-            entries: self.entries,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileSpan {
+    file_id: u16,
+    len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FreeSpan {
+    offset: usize,
+    len: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct MoveStats {
+    files_moved: usize,
+    blocks_moved: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct FragmentationStats {
+    free_spans: usize,
+    largest_free_span: usize,
+    files_moved: usize,
+    blocks_moved: usize,
+}
+
+type Candidates<'a> = dyn Iterator<Item = FreeSpan> + 'a;
+
+// Candidates are free spans left of the file that are large enough to hold it,
+// ordered by offset. Returning `None` keeps the file in place.
+trait AllocationPolicy {
+    fn select(&mut self, file: &FileSpan, candidates: &mut Candidates) -> Option<FreeSpan>;
+
+    // A single pass over the files is enough when none of them gets retried
+    fn single_pass(&self) -> bool {
+        false
+    }
+}
+
+struct FirstFit;
+
+impl AllocationPolicy for FirstFit {
+    fn select(&mut self, _file: &FileSpan, candidates: &mut Candidates) -> Option<FreeSpan> {
+        candidates.next()
+    }
+}
+
+struct BestFit;
+
+impl AllocationPolicy for BestFit {
+    fn select(&mut self, _file: &FileSpan, candidates: &mut Candidates) -> Option<FreeSpan> {
+        candidates.min_by_key(|span| span.len)
+    }
+}
+
+struct WorstFit;
+
+impl AllocationPolicy for WorstFit {
+    fn select(&mut self, _file: &FileSpan, candidates: &mut Candidates) -> Option<FreeSpan> {
+        // The first of the largest spans, as `max_by_key` would keep the last
+        candidates.min_by_key(|span| Reverse(span.len))
+    }
+}
+
+#[derive(Default)]
+struct NextFit {
+    cursor: usize,
+}
+
+impl AllocationPolicy for NextFit {
+    fn select(&mut self, _file: &FileSpan, candidates: &mut Candidates) -> Option<FreeSpan> {
+        let mut first = None;
+        let span = candidates
+            .inspect(|span| {
+                first.get_or_insert(*span);
+            })
+            .find(|span| span.offset >= self.cursor)
+            .or(first)?;
+        self.cursor = span.offset;
+        Some(span)
+    }
+}
+
+// Each file gets a single attempt, in the puzzle's decreasing file id order,
+// so it is moved at most once even when the allocation runs to a fixpoint.
+struct MoveOnce<P> {
+    policy: P,
+    moved: HashSet<u16>,
+}
+
+impl<P: AllocationPolicy> MoveOnce<P> {
+    pub fn new(policy: P) -> Self {
+        Self {
+            policy,
+            moved: HashSet::new(),
+        }
+    }
+}
+
+impl<P: AllocationPolicy> AllocationPolicy for MoveOnce<P> {
+    fn select(&mut self, file: &FileSpan, candidates: &mut Candidates) -> Option<FreeSpan> {
+        if !self.moved.insert(file.file_id) {
+            return None;
+        }
+        self.policy.select(file, candidates)
+    }
+
+    fn single_pass(&self) -> bool {
+        true
+    }
+}
+
+// Steps through the puzzle's defragmentation one file attempt at a time,
+// caching the last state so that stepping forward stays incremental.
+struct CompactionSimulation {
+    initial: DiskMap,
+    max_file_id: u16,
+    width: usize,
+    cache: RefCell<(usize, DiskMap, MoveOnce<FirstFit>)>,
+}
+
+impl CompactionSimulation {
+    pub fn new(initial: DiskMap, width: usize) -> Self {
+        let max_file_id = initial
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                DiskEntry::Block { file_id, .. } => Some(*file_id),
+                DiskEntry::Free { .. } => None,
+            })
+            .max()
+            .unwrap_or_default();
+        let cache = RefCell::new((0, initial.clone(), MoveOnce::new(FirstFit)));
+        Self {
+            initial,
+            max_file_id,
+            width,
+            cache,
+        }
+    }
+}
+
+impl Simulation for CompactionSimulation {
+    type State = DiskMap;
+
+    fn state(&self, step: usize) -> DiskMap {
+        // Every file has been attempted once all of them were stepped through
+        let step = step.min(self.max_file_id as usize + 1);
+        let mut cache = self.cache.borrow_mut();
+        if cache.0 > step {
+            *cache = (0, self.initial.clone(), MoveOnce::new(FirstFit));
+        }
+        let (cached_step, disk_map, policy) = &mut *cache;
+        while *cached_step < step {
+            disk_map.move_file(self.max_file_id - *cached_step as u16, policy);
+            *cached_step += 1;
         }
+        disk_map.clone()
+    }
+
+    fn render(&self, disk_map: &DiskMap) -> Vec<String> {
+        let blocks = disk_map
+            .entries
+            .iter()
+            .flat_map(|entry| match *entry {
+                DiskEntry::Block { file_id, len } => {
+                    let c = char::from_digit(file_id as u32 % 36, 36).unwrap_or('#');
+                    std::iter::repeat_n(c, len)
+                }
+                DiskEntry::Free { len } => std::iter::repeat_n('.', len),
+            })
+            .collect_vec();
+        let mut lines = blocks
+            .chunks(self.width)
+            .map(|chunk| chunk.iter().collect::<String>())
+            .collect_vec();
+        lines.push(format!("{:?}", disk_map.fragmentation()));
+        lines
+    }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.max_file_id as usize + 2)
     }
 }
 
@@ -240,12 +597,68 @@ fn main() -> Result<()> {
     println!("Result = {}", result);
     // endregion
 
+    // region Allocation policies
+    println!("\n=== Allocation policies ===");
+
+    fn compare_policies<R: BufRead>(reader: R) -> Result<()> {
+        let line = reader.lines().next().expect("empty input")?;
+        let disk_map = || DiskMap::from_compact_str(&line);
+
+        let runs = [
+            ("blocks", disk_map().compacted()),
+            (
+                "first-fit",
+                disk_map().allocated(&mut MoveOnce::new(FirstFit)),
+            ),
+            (
+                "best-fit",
+                disk_map().allocated(&mut MoveOnce::new(BestFit)),
+            ),
+            (
+                "worst-fit",
+                disk_map().allocated(&mut MoveOnce::new(WorstFit)),
+            ),
+            (
+                "next-fit",
+                disk_map().allocated(&mut MoveOnce::new(NextFit::default())),
+            ),
+        ];
+        for (name, disk_map) in runs {
+            println!(
+                "{:>10}: checksum = {}, {:?}",
+                name,
+                disk_map.checksum(),
+                disk_map.fragmentation()
+            );
+        }
+        Ok(())
+    }
+
+    let input_file = BufReader::new(File::open(INPUT_FILE)?);
+    time_snippet!(compare_policies(input_file)?);
+    // endregion
+
+    if std::env::args().any(|arg| arg == "--inspect") {
+        let line = BufReader::new(File::open(INPUT_FILE)?)
+            .lines()
+            .next()
+            .expect("empty input")?;
+        let simulation = CompactionSimulation::new(DiskMap::from_compact_str(&line), 200);
+        let mut viewer = Viewer::new(&simulation)
+            .with_search(|disk_map| disk_map.fragmentation().largest_free_span >= 9);
+        viewer.run()?;
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{DiskEntry, DiskMap};
+    use crate::{
+        BestFit, CompactionSimulation, DiskEntry, DiskMap, FirstFit, FragmentationStats, MoveOnce,
+        NextFit, WorstFit,
+    };
+    use adv_code_2024::viewer::Simulation;
 
     #[test]
     fn test_disk_map_from_str() {
@@ -273,4 +686,136 @@ mod tests {
         let dm = DiskMap::from_compact_str("2333133121414131402");
         assert_eq!(dm.compacted().to_string(), "0099811188827773336446555566");
     }
+
+    #[test]
+    fn test_disk_map_allocation_policies() {
+        let dm = DiskMap::from_compact_str("2333133121414131402");
+        let defragged = dm.defrag_compacted();
+        assert_eq!(
+            defragged.to_string(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert_eq!(defragged.checksum(), 2858);
+        assert_eq!(
+            defragged.fragmentation(),
+            FragmentationStats {
+                free_spans: 5,
+                largest_free_span: 5,
+                files_moved: 4,
+                blocks_moved: 8,
+            }
+        );
+
+        let dm = DiskMap::from_compact_str("1313165");
+        assert_eq!(dm.to_string(), "0...1...2......33333");
+        assert_eq!(
+            dm.clone()
+                .allocated(&mut MoveOnce::new(FirstFit))
+                .to_string(),
+            "021......33333......"
+        );
+        assert_eq!(
+            dm.allocated(&mut FirstFit).to_string(),
+            "02133333............"
+        );
+
+        let dm = DiskMap::from_compact_str("1412132");
+        assert_eq!(dm.to_string(), "0....1..2...33");
+        assert_eq!(
+            dm.clone()
+                .allocated(&mut MoveOnce::new(BestFit))
+                .to_string(),
+            "021...33......"
+        );
+        assert_eq!(
+            dm.allocated(&mut MoveOnce::new(WorstFit)).to_string(),
+            "03321........."
+        );
+
+        let dm = DiskMap::from_compact_str("1114113");
+        assert_eq!(dm.to_string(), "0.1....2.333");
+        assert_eq!(
+            dm.clone()
+                .allocated(&mut MoveOnce::new(FirstFit))
+                .to_string(),
+            "021333......"
+        );
+        assert_eq!(
+            dm.allocated(&mut MoveOnce::new(NextFit::default()))
+                .to_string(),
+            "01.3332....."
+        );
+    }
+
+    #[test]
+    fn test_compaction_simulation() {
+        let dm = DiskMap::from_compact_str("2333133121414131402");
+        let simulation = CompactionSimulation::new(dm, 20);
+        assert_eq!(simulation.steps(), Some(11));
+        assert_eq!(
+            simulation.render(&simulation.state(1)),
+            vec![
+                "0099.111...2...333.4",
+                "4.5555.6666.777.8888",
+                "..",
+                "FragmentationStats { free_spans: 8, largest_free_span: 3, files_moved: 1, blocks_moved: 2 }"
+            ]
+        );
+        assert_eq!(
+            simulation.state(10).to_string(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert_eq!(
+            simulation.state(100).to_string(),
+            simulation.state(10).to_string()
+        );
+        assert_eq!(
+            simulation.state(2).to_string(),
+            "0099.111...2...333.44.5555.6666.777.8888.."
+        );
+    }
+
+    #[test]
+    fn test_disk_map_compact_stats() {
+        let dm = DiskMap::from_compact_str("12345").compacted();
+        assert_eq!(
+            dm.fragmentation(),
+            FragmentationStats {
+                free_spans: 0,
+                largest_free_span: 0,
+                files_moved: 1,
+                blocks_moved: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_disk_map_long_free_spans() {
+        let mut dm = DiskMap::default();
+        dm.entries.push(DiskEntry::Block { file_id: 0, len: 1 });
+        dm.entries.push(DiskEntry::Free { len: 200 });
+        dm.entries.push(DiskEntry::Block { file_id: 1, len: 5 });
+        dm.entries.push(DiskEntry::Free { len: 100 });
+        dm.entries.push(DiskEntry::Block { file_id: 2, len: 3 });
+
+        // Both files move into the first span, leaving 300 free blocks behind
+        let defragged = dm.allocated(&mut MoveOnce::new(FirstFit));
+        assert_eq!(defragged.to_string().trim_end_matches('.'), "022211111");
+        assert!(matches!(
+            defragged.entries[3..],
+            [DiskEntry::Free { len: 300 }]
+        ));
+        assert_eq!(defragged.checksum(), 2 * (1 + 2 + 3) + (4 + 5 + 6 + 7 + 8));
+    }
+    #[test]
+    fn test_disk_map_move_files_in_any_order() {
+        let mut dm = DiskMap::from_compact_str("2333133121414131402");
+        assert!(dm.move_file(2, &mut FirstFit));
+        assert_eq!(dm.to_string(), "002..111.......333.44.5555.6666.777.888899");
+
+        // File 9 lies right of the last move, so the lookup has to go forward
+        assert!(dm.move_file(9, &mut FirstFit));
+        assert!(!dm.move_file(0, &mut FirstFit));
+        assert_eq!(dm.to_string(), "00299111.......333.44.5555.6666.777.8888..");
+    }
 }
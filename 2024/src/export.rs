@@ -0,0 +1,392 @@
+use crate::viewer::Simulation;
+use anyhow::*;
+use std::collections::HashMap;
+use std::io::Write;
+use std::result::Result::Ok;
+
+pub type Rgb = [u8; 3];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Palette {
+    colors: HashMap<char, Rgb>,
+}
+
+impl Palette {
+    pub fn with(mut self, c: char, color: Rgb) -> Self {
+        self.colors.insert(c, color);
+        self
+    }
+
+    // Unmapped symbols get a stable color derived from the symbol itself, so
+    // region maps stay readable without listing every plot type.
+    pub fn color(&self, c: char) -> Rgb {
+        if let Some(color) = self.colors.get(&c) {
+            return *color;
+        }
+        let h = (c as u32).wrapping_mul(2654435761);
+        [
+            64 + (h >> 24) as u8 % 192,
+            64 + (h >> 16) as u8 % 192,
+            64 + (h >> 8) as u8 % 192,
+        ]
+    }
+}
+
+pub fn points_to_grid(
+    points: impl Iterator<Item = (isize, isize)>,
+    area: (isize, isize),
+) -> Vec<String> {
+    let mut grid = vec![vec!['.'; area.0 as usize]; area.1 as usize];
+    for (x, y) in points {
+        if (0..area.0).contains(&x) && (0..area.1).contains(&y) {
+            grid[y as usize][x as usize] = '#';
+        }
+    }
+    grid.into_iter().map(String::from_iter).collect()
+}
+
+pub struct Exporter {
+    palette: Palette,
+    scale: usize,
+}
+
+impl Exporter {
+    pub fn new(palette: Palette) -> Self {
+        Self { palette, scale: 1 }
+    }
+
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn rasterize(&self, grid: &[String]) -> Image {
+        let columns = grid
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or_default();
+        let width = columns * self.scale;
+        let height = grid.len() * self.scale;
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in grid {
+            let mut line = Vec::with_capacity(width);
+            for c in row.chars().chain(std::iter::repeat(' ')).take(columns) {
+                line.extend(std::iter::repeat_n(self.palette.color(c), self.scale));
+            }
+            for _ in 0..self.scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn write_ppm<W: Write>(&self, grid: &[String], out: &mut W) -> Result<()> {
+        let image = self.rasterize(grid);
+        write!(out, "P6\n{} {}\n255\n", image.width, image.height)?;
+        out.write_all(&image.pixels.concat())?;
+        Ok(())
+    }
+
+    pub fn write_png<W: Write>(&self, grid: &[String], out: &mut W) -> Result<()> {
+        let image = self.rasterize(grid);
+        out.write_all(PNG_SIGNATURE)?;
+        write_chunk(out, b"IHDR", &ihdr(&image))?;
+        write_chunk(out, b"IDAT", &zlib_stored(&scanlines(&image)))?;
+        write_chunk(out, b"IEND", &[])?;
+        Ok(())
+    }
+
+    // Writes an animated PNG, every frame shown for `delay_ms` and looping
+    // forever. All frames are padded to the size of the largest one.
+    pub fn write_apng<W: Write>(
+        &self,
+        frames: &[Vec<String>],
+        delay_ms: u16,
+        out: &mut W,
+    ) -> Result<()> {
+        let images = frames
+            .iter()
+            .map(|grid| self.rasterize(grid))
+            .collect::<Vec<_>>();
+        let width = images.iter().map(|i| i.width).max().unwrap_or_default();
+        let height = images.iter().map(|i| i.height).max().unwrap_or_default();
+        ensure!(!images.is_empty(), "no frames to export");
+
+        let images = images
+            .into_iter()
+            .map(|image| pad(image, width, height, self.palette.color(' ')))
+            .collect::<Vec<_>>();
+
+        out.write_all(PNG_SIGNATURE)?;
+        write_chunk(out, b"IHDR", &ihdr(&images[0]))?;
+        let mut actl = (images.len() as u32).to_be_bytes().to_vec();
+        actl.extend(0u32.to_be_bytes());
+        write_chunk(out, b"acTL", &actl)?;
+
+        let mut sequence = 0u32;
+        for (i, image) in images.iter().enumerate() {
+            let mut fctl = sequence.to_be_bytes().to_vec();
+            fctl.extend((width as u32).to_be_bytes());
+            fctl.extend((height as u32).to_be_bytes());
+            fctl.extend(0u32.to_be_bytes());
+            fctl.extend(0u32.to_be_bytes());
+            fctl.extend(delay_ms.to_be_bytes());
+            fctl.extend(1000u16.to_be_bytes());
+            fctl.extend([0, 0]);
+            write_chunk(out, b"fcTL", &fctl)?;
+            sequence += 1;
+
+            let data = zlib_stored(&scanlines(image));
+            if i == 0 {
+                write_chunk(out, b"IDAT", &data)?;
+            } else {
+                let mut fdat = sequence.to_be_bytes().to_vec();
+                fdat.extend(data);
+                write_chunk(out, b"fdAT", &fdat)?;
+                sequence += 1;
+            }
+        }
+
+        write_chunk(out, b"IEND", &[])?;
+        Ok(())
+    }
+
+    // Renders the given steps until the simulation ends. At most `max_frames`
+    // are written, so endless simulations and step iterators stay bounded.
+    pub fn write_simulation<S: Simulation, W: Write>(
+        &self,
+        simulation: &S,
+        frames: impl Iterator<Item = usize>,
+        max_frames: usize,
+        delay_ms: u16,
+        out: &mut W,
+    ) -> Result<()> {
+        let frames = frames
+            .take_while(|step| simulation.steps().is_none_or(|steps| *step < steps))
+            .take(max_frames)
+            .map(|step| simulation.render(&simulation.state(step)))
+            .collect::<Vec<_>>();
+        self.write_apng(&frames, delay_ms, out)
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn pad(mut image: Image, width: usize, height: usize, background: Rgb) -> Image {
+    if image.width == width && image.height == height {
+        return image;
+    }
+    let mut pixels = vec![background; width * height];
+    for (y, row) in image.pixels.chunks(image.width.max(1)).enumerate() {
+        pixels[y * width..y * width + row.len()].copy_from_slice(row);
+    }
+    image.pixels = pixels;
+    image.width = width;
+    image.height = height;
+    image
+}
+
+fn ihdr(image: &Image) -> Vec<u8> {
+    let mut data = (image.width as u32).to_be_bytes().to_vec();
+    data.extend((image.height as u32).to_be_bytes());
+    // 8 bit RGB, default compression and filter, no interlacing
+    data.extend([8, 2, 0, 0, 0]);
+    data
+}
+
+fn scanlines(image: &Image) -> Vec<u8> {
+    let mut data = Vec::with_capacity(image.height * (image.width * 3 + 1));
+    for row in image.pixels.chunks(image.width.max(1)) {
+        data.push(0);
+        data.extend(row.concat());
+    }
+    data
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()).copied());
+    out.write_all(&crc.to_be_bytes())?;
+    Ok(())
+}
+
+fn crc32(bytes: impl Iterator<Item = u8>) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// Wraps data into a zlib stream of uncompressed deflate blocks, which every
+// PNG decoder accepts and which needs no compressor.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        Palette::default()
+            .with('.', [0, 0, 0])
+            .with('#', [255, 255, 255])
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND".iter().copied()), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let grid = points_to_grid([(0, 0), (1, 1), (5, 5)].into_iter(), (2, 2));
+        assert_eq!(grid, vec!["#.", ".#"]);
+
+        let mut out = vec![];
+        Exporter::new(palette())
+            .with_scale(2)
+            .write_ppm(&grid, &mut out)
+            .unwrap();
+        assert!(out.starts_with(b"P6\n4 4\n255\n"));
+        let pixels = &out[11..];
+        assert_eq!(pixels.len(), 4 * 4 * 3);
+        assert_eq!(&pixels[..6], &[255; 6]);
+        assert_eq!(&pixels[6..12], &[0; 6]);
+        assert_eq!(&pixels[36..42], &[0; 6]);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut out = vec![];
+        Exporter::new(palette())
+            .write_png(&["#.".to_string()], &mut out)
+            .unwrap();
+
+        assert!(out.starts_with(PNG_SIGNATURE));
+        assert_eq!(&out[12..16], b"IHDR");
+        assert_eq!(&out[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(&out[37..41], b"IDAT");
+        // zlib header, a single final stored block of 7 bytes, the scanline
+        let idat = &out[41..41 + 18];
+        assert_eq!(&idat[..7], &[0x78, 0x01, 1, 7, 0, 0xf8, 0xff]);
+        assert_eq!(&idat[7..14], &[0, 255, 255, 255, 0, 0, 0]);
+        assert!(out.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn test_write_apng() {
+        let frames = vec![
+            vec!["#.".to_string()],
+            vec![".#".to_string(), "#".to_string()],
+        ];
+        let mut out = vec![];
+        Exporter::new(palette())
+            .write_apng(&frames, 100, &mut out)
+            .unwrap();
+
+        let kinds = chunk_kinds(&out);
+        assert_eq!(
+            kinds,
+            vec!["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "IEND"]
+        );
+        // Frames are padded to the largest one
+        assert_eq!(&out[16..24], &[0, 0, 0, 2, 0, 0, 0, 2]);
+        assert!(Exporter::new(palette())
+            .write_apng(&[], 100, &mut vec![])
+            .is_err());
+    }
+
+    struct Counter;
+
+    impl Simulation for Counter {
+        type State = usize;
+
+        fn state(&self, step: usize) -> usize {
+            step
+        }
+
+        fn render(&self, state: &usize) -> Vec<String> {
+            vec!["#".repeat(*state % 3 + 1)]
+        }
+    }
+
+    #[test]
+    fn test_write_simulation() {
+        // Endless simulations stop at the frame limit
+        let mut out = vec![];
+        Exporter::new(palette())
+            .write_simulation(&Counter, 0.., 3, 100, &mut out)
+            .unwrap();
+        let kinds = chunk_kinds(&out);
+        assert_eq!(kinds.iter().filter(|kind| *kind == "fcTL").count(), 3);
+        assert!(Exporter::new(palette())
+            .write_simulation(&Counter, 0.., 0, 100, &mut vec![])
+            .is_err());
+    }
+
+    fn chunk_kinds(png: &[u8]) -> Vec<String> {
+        let mut kinds = vec![];
+        let mut offset = PNG_SIGNATURE.len();
+        while offset < png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind = &png[offset + 4..offset + 8];
+            let crc =
+                u32::from_be_bytes(png[offset + 8 + len..offset + 12 + len].try_into().unwrap());
+            assert_eq!(
+                crc,
+                crc32(png[offset + 4..offset + 8 + len].iter().copied())
+            );
+            kinds.push(String::from_utf8(kind.to_vec()).unwrap());
+            offset += len + 12;
+        }
+        kinds
+    }
+}
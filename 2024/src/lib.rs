@@ -1,12 +1,49 @@
 use itertools::Itertools;
 use std::slice::Iter;
 
+pub mod export;
+pub mod viewer;
+
 pub fn start_day(day: &str) {
     println!("Advent of Code 2024 - Day {:0>2}", day);
 }
 
 // Additional common functions
 
+pub fn gcd(a: i128, b: i128) -> i128 {
+    extended_gcd(a, b).0
+}
+
+// Returns `(g, x, y)` with `a * x + b * y == g` and `g >= 0`
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        if a < 0 {
+            (-a, -1, 0)
+        } else {
+            (a, 1, 0)
+        }
+    } else {
+        let (g, x, y) = extended_gcd(b, a.rem_euclid(b));
+        (g, y, x - a.div_euclid(b) * y)
+    }
+}
+
+// Combines `x = r (mod m)` congruences into a single `(r, m)`, moduli need
+// not be coprime. Returns `None` when the congruences contradict each other.
+pub fn crt(congruences: &[(i128, i128)]) -> Option<(i128, i128)> {
+    congruences
+        .iter()
+        .try_fold((0i128, 1i128), |(r1, m1), &(r2, m2)| {
+            let (g, p, _) = extended_gcd(m1, m2);
+            if (r2 - r1) % g != 0 {
+                return None;
+            }
+            let lcm = m1 / g * m2;
+            let k = ((r2 - r1) / g * p).rem_euclid(m2 / g);
+            Some(((r1 + m1 * k).rem_euclid(lcm), lcm))
+        })
+}
+
 pub type Position = (usize, usize);
 pub type Rectangle = (Position, Position);
 
@@ -184,4 +221,25 @@ mod tests {
     fn it_works() {
         start_day("00");
     }
+
+    #[test]
+    fn test_extended_gcd() {
+        for (a, b) in [(240, 46), (46, 240), (-12, 18), (7, 0), (0, -5), (17, 5)] {
+            let (g, x, y) = extended_gcd(a, b);
+            assert_eq!(a * x + b * y, g);
+            assert!(g >= 0);
+        }
+        assert_eq!(gcd(240, 46), 2);
+        assert_eq!(gcd(-12, 18), 6);
+        assert_eq!(gcd(0, -5), 5);
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(&[(2, 3), (3, 5), (2, 7)]), Some((23, 105)));
+        assert_eq!(crt(&[(1, 4), (3, 6)]), Some((9, 12)));
+        assert_eq!(crt(&[(1, 4), (2, 6)]), None);
+        assert_eq!(crt(&[(70, 101), (40, 103)]).map(|(r, _)| r % 101), Some(70));
+        assert_eq!(crt(&[]), Some((0, 1)));
+    }
 }
@@ -0,0 +1,298 @@
+use anyhow::*;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::result::Result::Ok;
+use std::{thread, time};
+use termion::{clear, cursor, event::Key, input::TermRead, raw::IntoRawMode};
+
+pub trait Simulation {
+    type State;
+
+    fn state(&self, step: usize) -> Self::State;
+
+    fn render(&self, state: &Self::State) -> Vec<String>;
+
+    // Number of steps for simulations that end, `None` for endless ones
+    fn steps(&self) -> Option<usize> {
+        None
+    }
+}
+
+type Predicate<'a, S> = Box<dyn Fn(&S) -> bool + 'a>;
+
+pub struct Viewer<'a, S: Simulation> {
+    simulation: &'a S,
+    step: usize,
+    playing: bool,
+    speed: usize,
+    bookmarks: BTreeSet<usize>,
+    search: Option<Predicate<'a, S::State>>,
+    search_limit: usize,
+    input: String,
+    message: String,
+}
+
+impl<'a, S: Simulation> Viewer<'a, S> {
+    pub fn new(simulation: &'a S) -> Self {
+        Self {
+            simulation,
+            step: 0,
+            playing: false,
+            speed: 1,
+            bookmarks: BTreeSet::new(),
+            search: None,
+            search_limit: 100_000,
+            input: String::new(),
+            message: String::new(),
+        }
+    }
+
+    pub fn with_search<F: Fn(&S::State) -> bool + 'a>(mut self, predicate: F) -> Self {
+        self.search = Some(Box::new(predicate));
+        self
+    }
+
+    pub fn with_search_limit(mut self, limit: usize) -> Self {
+        self.search_limit = limit;
+        self
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn jump_to(&mut self, step: usize) {
+        self.step = match self.simulation.steps() {
+            Some(steps) => step.min(steps.saturating_sub(1)),
+            None => step,
+        };
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        self.jump_to(self.step.saturating_add_signed(delta));
+    }
+
+    fn find_next(&self, forward: bool) -> Option<usize> {
+        let predicate = self.search.as_ref()?;
+        let matches = |step: &usize| predicate(&self.simulation.state(*step));
+        if forward {
+            let last = match self.simulation.steps() {
+                Some(steps) => steps.min(self.step + 1 + self.search_limit),
+                None => self.step + 1 + self.search_limit,
+            };
+            (self.step + 1..last).find(matches)
+        } else {
+            let first = self.step.saturating_sub(self.search_limit);
+            (first..self.step).rev().find(matches)
+        }
+    }
+
+    // Returns `false` when the viewer should quit
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        self.message.clear();
+        match key {
+            Key::Char('q') => return false,
+            Key::Right => self.move_by(1),
+            Key::Left => self.move_by(-1),
+            Key::PageDown => self.move_by(100),
+            Key::PageUp => self.move_by(-100),
+            Key::Home => self.jump_to(0),
+            Key::Char(' ') => self.playing = !self.playing,
+            Key::Char('+') => self.speed = self.speed.saturating_mul(2),
+            Key::Char('-') => self.speed = (self.speed / 2).max(1),
+            Key::Char(c) if c.is_ascii_digit() => self.input.push(c),
+            Key::Backspace => {
+                self.input.pop();
+            }
+            Key::Char('\n') | Key::Char('g') => {
+                if let Ok(step) = self.input.parse::<usize>() {
+                    self.jump_to(step);
+                }
+                self.input.clear();
+            }
+            Key::Esc => self.input.clear(),
+            // Toggling must not move the removal into a guard
+            #[allow(clippy::collapsible_match)]
+            Key::Char('b') => {
+                if !self.bookmarks.remove(&self.step) {
+                    self.bookmarks.insert(self.step);
+                }
+            }
+            Key::Char(']') => {
+                if let Some(step) = self.bookmarks.range(self.step + 1..).next() {
+                    self.step = *step;
+                }
+            }
+            Key::Char('[') => {
+                if let Some(step) = self.bookmarks.range(..self.step).next_back() {
+                    self.step = *step;
+                }
+            }
+            Key::Char(c @ ('n' | 'N')) => {
+                self.playing = false;
+                match self.find_next(c == 'n') {
+                    Some(step) => self.step = step,
+                    None if self.search.is_none() => self.message = "No search predicate".into(),
+                    None => self.message = "No matching frame".into(),
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn tick(&mut self) {
+        if !self.playing {
+            return;
+        }
+        let step = self.step.saturating_add(self.speed);
+        self.jump_to(step);
+        if self
+            .simulation
+            .steps()
+            .is_some_and(|steps| step + 1 >= steps)
+        {
+            self.playing = false;
+        }
+    }
+
+    fn status(&self) -> String {
+        let bookmarks = self
+            .bookmarks
+            .iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "Step {}{} | {} x{} | bookmarks [{}] | goto: {} {}",
+            self.step,
+            self.simulation
+                .steps()
+                .map(|steps| format!("/{}", steps.saturating_sub(1)))
+                .unwrap_or_default(),
+            if self.playing { "playing" } else { "paused" },
+            self.speed,
+            bookmarks,
+            self.input,
+            self.message,
+        )
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        const HELP: &str = "Left/Right/PgUp/PgDn step, digits+Enter jump, Space play, +/- speed, \
+                            n/N search, b bookmark, [/] bookmarks, q quit";
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock().into_raw_mode()?;
+        let mut keys = termion::async_stdin().keys();
+        let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+
+        loop {
+            write!(stdout, "{}{}", clear::All, cursor::Goto(1, 1))?;
+            let state = self.simulation.state(self.step);
+            for line in self
+                .simulation
+                .render(&state)
+                .iter()
+                .take(height.saturating_sub(3) as usize)
+            {
+                let line = line.chars().take(width as usize).collect::<String>();
+                write!(stdout, "{}\r\n", line)?;
+            }
+            write!(stdout, "\r\n{}\r\n{}", self.status(), HELP)?;
+            stdout.flush()?;
+
+            let mut has_input = false;
+            for key in keys.by_ref() {
+                has_input = true;
+                if !self.handle_key(key?) {
+                    return Ok(());
+                }
+            }
+            if !has_input {
+                self.tick();
+            }
+
+            // Add a slight delay to avoid overwhelming the terminal
+            let delay = if self.playing { 50 } else { 20 };
+            thread::sleep(time::Duration::from_millis(delay));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        steps: Option<usize>,
+    }
+
+    impl Simulation for Counter {
+        type State = usize;
+
+        fn state(&self, step: usize) -> usize {
+            step * 3
+        }
+
+        fn render(&self, state: &usize) -> Vec<String> {
+            vec![state.to_string()]
+        }
+
+        fn steps(&self) -> Option<usize> {
+            self.steps
+        }
+    }
+
+    #[test]
+    fn test_viewer_navigation() {
+        let simulation = Counter { steps: Some(50) };
+        let mut viewer = Viewer::new(&simulation);
+
+        viewer.handle_key(Key::Left);
+        assert_eq!(viewer.step(), 0);
+        viewer.handle_key(Key::PageDown);
+        assert_eq!(viewer.step(), 49);
+
+        for key in [Key::Char('1'), Key::Char('2'), Key::Char('\n')] {
+            viewer.handle_key(key);
+        }
+        assert_eq!(viewer.step(), 12);
+
+        viewer.handle_key(Key::Char('b'));
+        viewer.jump_to(30);
+        viewer.handle_key(Key::Char('b'));
+        viewer.jump_to(20);
+        viewer.handle_key(Key::Char(']'));
+        assert_eq!(viewer.step(), 30);
+        viewer.handle_key(Key::Char('['));
+        assert_eq!(viewer.step(), 12);
+
+        assert!(!viewer.handle_key(Key::Char('q')));
+    }
+
+    #[test]
+    fn test_viewer_play_and_search() {
+        let simulation = Counter { steps: None };
+        let mut viewer = Viewer::new(&simulation).with_search(|state| state % 21 == 0);
+
+        viewer.tick();
+        assert_eq!(viewer.step(), 0);
+        viewer.handle_key(Key::Char(' '));
+        viewer.handle_key(Key::Char('+'));
+        viewer.tick();
+        viewer.tick();
+        assert_eq!(viewer.step(), 4);
+
+        viewer.handle_key(Key::Char('n'));
+        assert_eq!(viewer.step(), 7);
+        viewer.handle_key(Key::Char('n'));
+        assert_eq!(viewer.step(), 14);
+        viewer.handle_key(Key::Char('N'));
+        assert_eq!(viewer.step(), 7);
+
+        let mut viewer = Viewer::new(&simulation).with_search_limit(5);
+        viewer.handle_key(Key::Char('n'));
+        assert_eq!(viewer.step(), 0);
+    }
+}